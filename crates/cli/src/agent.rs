@@ -0,0 +1,385 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Error, ErrorKind};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use crate::eventlog::{self, LogEntry};
+use crate::webhook::{self, Change};
+use crate::{config, notify};
+use crate::instance::{batch_from_env, InstanceStore, Status, BATCH_ENV};
+use crate::AgentCommands;
+
+/// How often the watcher samples the agent's CPU usage
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(500);
+/// CPU ticks per sample above which the agent counts as busy
+const ACTIVE_TICKS: u64 = 2;
+/// How long the agent has to be idle before it counts as waiting for input
+const IDLE_AFTER: Duration = Duration::from_secs(3);
+
+/// The coding agent running in a pane
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum AgentKind {
+    #[default]
+    Claude,
+    Codex,
+    Aider,
+    Gemini,
+    /// Any other command, set with `[agent] command`
+    Custom,
+}
+
+impl AgentKind {
+    pub fn default_command(&self) -> Option<&'static str> {
+        match self {
+            AgentKind::Claude => Some("claude"),
+            AgentKind::Codex => Some("codex"),
+            AgentKind::Aider => Some("aider"),
+            AgentKind::Gemini => Some("gemini"),
+            AgentKind::Custom => None,
+        }
+    }
+
+    /// Arguments that start the agent on `prompt` while keeping it interactive
+    /// where the agent supports that
+    pub fn prompt_args(&self, prompt: &str) -> Vec<String> {
+        match self {
+            AgentKind::Gemini => vec!["--prompt-interactive".to_string(), prompt.to_string()],
+            // Aider has no interactive initial prompt; it handles the message and exits
+            AgentKind::Aider => vec!["--message".to_string(), prompt.to_string()],
+            AgentKind::Claude | AgentKind::Codex | AgentKind::Custom => vec![prompt.to_string()],
+        }
+    }
+
+    /// Claude Code reports its status through hooks; other agents run under
+    /// `maestro agent run`, which derives their status from CPU activity
+    pub fn has_hooks(&self) -> bool {
+        *self == AgentKind::Claude
+    }
+}
+
+impl std::fmt::Display for AgentKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AgentKind::Claude => write!(f, "claude"),
+            AgentKind::Codex => write!(f, "codex"),
+            AgentKind::Aider => write!(f, "aider"),
+            AgentKind::Gemini => write!(f, "gemini"),
+            AgentKind::Custom => write!(f, "custom"),
+        }
+    }
+}
+
+/// Command-line overrides for the configured agent
+#[derive(Debug, Default)]
+pub struct AgentOptions {
+    pub kind: Option<AgentKind>,
+    /// Extra arguments, split like a shell would
+    pub args: Option<String>,
+    /// Initial prompt for the agent
+    pub prompt: Option<String>,
+    /// Batch the instance is registered under (see `maestro fanout`)
+    pub batch: Option<String>,
+}
+
+/// The argv for the agent pane. Agents without hooks are wrapped in
+/// `maestro agent run` so they still show up in the registry.
+pub fn pane_command(config: &config::Agent, opts: &AgentOptions) -> io::Result<Vec<String>> {
+    let configured_kind = config.kind.unwrap_or_default();
+    let kind = opts.kind.unwrap_or(configured_kind);
+
+    // A configured command belongs to the configured agent kind
+    let command = match &config.command {
+        Some(command) if kind == configured_kind => command.as_str(),
+        _ => kind.default_command().ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                "The custom agent needs a command: set [agent] command in the config",
+            )
+        })?,
+    };
+
+    let mut argv = split_words(command)?;
+    if let Some(args) = &opts.args {
+        argv.extend(split_words(args)?);
+    }
+    if argv.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidInput, "Agent command is empty"));
+    }
+    if let Some(prompt) = &opts.prompt {
+        argv.extend(kind.prompt_args(prompt));
+    }
+
+    if !kind.has_hooks() {
+        let maestro = std::env::current_exe()
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_else(|_| "maestro".to_string());
+        let wrapper = [maestro.as_str(), "agent", "run", "--kind", &kind.to_string(), "--"];
+        argv.splice(0..0, wrapper.iter().map(|s| s.to_string()));
+    }
+
+    // Zellij layouts can't set per-pane env vars, so go through `env`; the
+    // agent's hooks (or the wrapper) pick the batch up when registering
+    if let Some(batch) = &opts.batch {
+        argv.splice(0..0, ["env".to_string(), format!("{}={}", BATCH_ENV, batch)]);
+    }
+    Ok(argv)
+}
+
+fn split_words(s: &str) -> io::Result<Vec<String>> {
+    shlex::split(s).ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!("Cannot parse agent command '{}'", s),
+        )
+    })
+}
+
+pub fn run(command: AgentCommands) -> io::Result<()> {
+    match command {
+        AgentCommands::Run { kind, command } => cmd_run(kind, &command),
+        AgentCommands::Watch { pane_id, pid } => cmd_watch(pane_id, pid),
+    }
+}
+
+/// Register the pane, start a detached watcher, then become the agent
+fn cmd_run(kind: AgentKind, argv: &[String]) -> io::Result<()> {
+    let (program, args) = argv
+        .split_first()
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "No agent command given"))?;
+
+    let pane_id = crate::get_pane_id();
+    if let Some(pane_id) = pane_id {
+        let cwd = std::env::current_dir()?;
+        let folder = cwd
+            .file_name()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_else(|| cwd.to_string_lossy().to_string());
+        let store = InstanceStore::new();
+        let instance = store.register(
+            pane_id,
+            folder,
+            cwd.to_string_lossy().to_string(),
+            kind,
+            batch_from_env(),
+        )?;
+        webhook::emit(vec![Change::Registered(instance)]);
+        eventlog::record(&LogEntry::new("register", pane_id, store.get(pane_id).as_ref()));
+
+        // The watcher gets its own process group so Ctrl-C in the pane only
+        // reaches the agent
+        Command::new(std::env::current_exe()?)
+            .args(["agent", "watch", "--pane-id"])
+            .arg(pane_id.to_string())
+            .arg("--pid")
+            .arg(std::process::id().to_string())
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .process_group(0)
+            .spawn()?;
+    }
+
+    // exec keeps our pid, so the watcher follows the agent itself
+    let err = Command::new(program).args(args).exec();
+    if let Some(pane_id) = pane_id {
+        let _ = unregister(&InstanceStore::new(), pane_id);
+    }
+    Err(Error::new(
+        err.kind(),
+        format!("Cannot start {}: {}", program, err),
+    ))
+}
+
+/// Track the agent's CPU activity until it exits, updating its status
+fn cmd_watch(pane_id: u32, pid: u32) -> io::Result<()> {
+    let store = InstanceStore::new();
+    let Some(start_time) = read_stat(pid).map(|s| s.start_time) else {
+        return unregister(&store, pane_id);
+    };
+
+    let mut status = Status::Running;
+    let mut last_ticks = tree_ticks(&read_all_stats(), pid);
+    let mut last_active = Instant::now();
+
+    loop {
+        std::thread::sleep(SAMPLE_INTERVAL);
+
+        // Stop once the agent is gone (or its pid was reused)
+        if read_stat(pid).is_none_or(|s| s.start_time != start_time) {
+            break;
+        }
+
+        let ticks = tree_ticks(&read_all_stats(), pid);
+        if ticks.saturating_sub(last_ticks) >= ACTIVE_TICKS {
+            last_active = Instant::now();
+        }
+        last_ticks = ticks;
+
+        let current = if last_active.elapsed() < IDLE_AFTER {
+            Status::Running
+        } else {
+            Status::Waiting
+        };
+        if current != status {
+            status = current;
+            let change = store.update_status(pane_id, status)?;
+            if change.is_some() && status == Status::Waiting {
+                notify::schedule(pane_id);
+            }
+            webhook::emit(change.into_iter().collect());
+            eventlog::record(&LogEntry::new("status", pane_id, store.get(pane_id).as_ref()));
+        }
+    }
+
+    let mut entry = LogEntry::new("unregister", pane_id, store.get(pane_id).as_ref());
+    entry.status = None;
+    unregister(&store, pane_id)?;
+    eventlog::record(&entry);
+    Ok(())
+}
+
+fn unregister(store: &InstanceStore, pane_id: u32) -> io::Result<()> {
+    let removed = store.unregister(pane_id)?;
+    webhook::emit(removed.into_iter().map(Change::Unregistered).collect());
+    Ok(())
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct ProcStat {
+    ppid: u32,
+    /// utime + stime, in clock ticks
+    ticks: u64,
+    start_time: u64,
+}
+
+/// Parse `/proc/<pid>/stat`
+fn parse_stat(line: &str) -> Option<ProcStat> {
+    // The command name may contain spaces and parens, so split after the last ')'
+    let rest = &line[line.rfind(')')? + 1..];
+    let fields: Vec<&str> = rest.split_whitespace().collect();
+    // Field numbers from proc(5), offset by the two fields before `rest`
+    let field = |n: usize| fields.get(n - 3).and_then(|f| f.parse::<u64>().ok());
+    Some(ProcStat {
+        ppid: field(4)? as u32,
+        ticks: field(14)? + field(15)?,
+        start_time: field(22)?,
+    })
+}
+
+fn read_stat(pid: u32) -> Option<ProcStat> {
+    parse_stat(&fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?)
+}
+
+fn read_all_stats() -> HashMap<u32, ProcStat> {
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return HashMap::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+        .filter_map(|pid| Some((pid, read_stat(pid)?)))
+        .collect()
+}
+
+/// CPU ticks used by `root` and all of its descendants
+fn tree_ticks(stats: &HashMap<u32, ProcStat>, root: u32) -> u64 {
+    let mut total = 0;
+    let mut pending = vec![root];
+    while let Some(pid) = pending.pop() {
+        if let Some(stat) = stats.get(&pid) {
+            total += stat.ticks;
+        }
+        pending.extend(
+            stats
+                .iter()
+                .filter(|(child, stat)| stat.ppid == pid && **child != pid)
+                .map(|(child, _)| *child),
+        );
+    }
+    total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_stat() {
+        let line = "4242 (my (weird) agent) S 100 4242 4242 0 -1 4194560 1234 0 0 0 \
+                    150 25 0 0 20 0 4 0 987654 0 0";
+        assert_eq!(
+            parse_stat(line),
+            Some(ProcStat {
+                ppid: 100,
+                ticks: 175,
+                start_time: 987654,
+            })
+        );
+        assert_eq!(parse_stat("garbage"), None);
+    }
+
+    #[test]
+    fn test_tree_ticks() {
+        let stat = |ppid, ticks| ProcStat {
+            ppid,
+            ticks,
+            start_time: 0,
+        };
+        let stats = HashMap::from([
+            (10, stat(1, 5)),
+            (11, stat(10, 7)),
+            (12, stat(11, 3)),
+            (20, stat(1, 100)),
+        ]);
+        assert_eq!(tree_ticks(&stats, 10), 15);
+        assert_eq!(tree_ticks(&stats, 99), 0);
+    }
+
+    #[test]
+    fn test_pane_command() {
+        let claude = config::Agent::default();
+        assert_eq!(
+            pane_command(&claude, &AgentOptions::default()).unwrap(),
+            vec!["claude"]
+        );
+
+        let configured = config::Agent {
+            kind: Some(AgentKind::Claude),
+            command: Some("claude --model opus".to_string()),
+        };
+        assert_eq!(
+            pane_command(&configured, &AgentOptions::default()).unwrap(),
+            vec!["claude", "--model", "opus"]
+        );
+
+        // Overriding the kind drops the configured command
+        let opts = AgentOptions {
+            kind: Some(AgentKind::Aider),
+            args: Some("--model 'gpt 4'".to_string()),
+            ..Default::default()
+        };
+        let argv = pane_command(&configured, &opts).unwrap();
+        assert_eq!(&argv[1..], ["agent", "run", "--kind", "aider", "--", "aider", "--model", "gpt 4"]);
+
+        let opts = AgentOptions {
+            kind: Some(AgentKind::Custom),
+            ..Default::default()
+        };
+        assert!(pane_command(&claude, &opts).is_err());
+
+        let opts = AgentOptions {
+            prompt: Some("fix the bug".to_string()),
+            batch: Some("fix".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            pane_command(&claude, &opts).unwrap(),
+            vec!["env", "MAESTRO_BATCH=fix", "claude", "fix the bug"]
+        );
+    }
+}
@@ -0,0 +1,215 @@
+use serde::Deserialize;
+use std::fs;
+use std::io::{self, Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config::main_worktree;
+
+const ISSUES_FILE: &str = ".beads/issues.jsonl";
+/// Git config key under `branch.<name>` linking a branch to its issue
+const ISSUE_KEY: &str = "maestro-issue";
+/// Longest title slug in a derived branch name
+const MAX_SLUG: usize = 40;
+
+/// An issue from the beads JSONL export
+#[derive(Debug, Clone, Deserialize)]
+pub struct Issue {
+    pub id: String,
+    pub title: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub status: Option<String>,
+}
+
+impl Issue {
+    /// Branch name for work on the issue, e.g. `app-x1-fix-login-redirect`
+    pub fn branch_name(&self) -> String {
+        let slug = slugify(&self.title);
+        if slug.is_empty() {
+            self.id.clone()
+        } else {
+            format!("{}-{}", self.id, slug)
+        }
+    }
+
+    /// Initial prompt for an agent working on the issue
+    pub fn prompt(&self) -> String {
+        let mut prompt = format!("Work on issue {}: {}", self.id, self.title);
+        if !self.description.trim().is_empty() {
+            prompt.push_str("\n\n");
+            prompt.push_str(self.description.trim());
+        }
+        prompt
+    }
+}
+
+/// Lowercase words of `title` joined by dashes, cut at a word boundary
+fn slugify(title: &str) -> String {
+    let mut slug = String::new();
+    let words = title
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|w| !w.is_empty());
+    for word in words {
+        if !slug.is_empty() && slug.len() + 1 + word.len() > MAX_SLUG {
+            break;
+        }
+        if !slug.is_empty() {
+            slug.push('-');
+        }
+        slug.push_str(&word.to_ascii_lowercase());
+    }
+    slug.truncate(MAX_SLUG);
+    slug
+}
+
+/// The issues file for `dir`: the worktree's own, or else the main worktree's
+fn issues_path(dir: &Path) -> Option<PathBuf> {
+    let toplevel = git(dir, &["rev-parse", "--show-toplevel"]).map(PathBuf::from);
+    toplevel
+        .into_iter()
+        .chain(main_worktree(dir))
+        .map(|root| root.join(ISSUES_FILE))
+        .find(|path| path.exists())
+}
+
+/// Find an issue by id in the repository containing `dir`. An id suffix such
+/// as `x1` matches `app-x1` if no other issue ends the same way.
+pub fn find_issue(dir: &Path, id: &str) -> io::Result<Issue> {
+    let path = issues_path(dir).ok_or_else(|| {
+        Error::new(ErrorKind::NotFound, format!("No {} in this repository", ISSUES_FILE))
+    })?;
+    let issues = parse_issues(&fs::read_to_string(&path)?);
+    match_issue(issues, id)
+}
+
+fn parse_issues(content: &str) -> Vec<Issue> {
+    content
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+fn match_issue(issues: Vec<Issue>, id: &str) -> io::Result<Issue> {
+    if let Some(issue) = issues.iter().find(|i| i.id == id) {
+        return Ok(issue.clone());
+    }
+    let suffix = format!("-{}", id);
+    let mut matches: Vec<Issue> = issues.into_iter().filter(|i| i.id.ends_with(&suffix)).collect();
+    match matches.len() {
+        0 => Err(Error::new(ErrorKind::NotFound, format!("No issue '{}'", id))),
+        1 => Ok(matches.remove(0)),
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "Issue id '{}' is ambiguous: {}",
+                id,
+                matches.iter().map(|i| i.id.as_str()).collect::<Vec<_>>().join(", ")
+            ),
+        )),
+    }
+}
+
+/// Remember which issue a branch works on
+pub fn link_branch(dir: &Path, branch: &str, issue_id: &str) -> io::Result<()> {
+    let key = format!("branch.{}.{}", branch, ISSUE_KEY);
+    let status = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["config", &key, issue_id])
+        .status()?;
+    if !status.success() {
+        return Err(Error::new(ErrorKind::Other, format!("git config {} failed", key)));
+    }
+    Ok(())
+}
+
+/// The issue a branch was started from, if any
+pub fn linked_issue(dir: &Path, branch: &str) -> Option<String> {
+    git(dir, &["config", &format!("branch.{}.{}", branch, ISSUE_KEY)])
+}
+
+/// Close an issue with `bd`, run in `dir`
+pub fn close_issue(dir: &Path, issue_id: &str, reason: &str) -> io::Result<()> {
+    let status = Command::new("bd")
+        .args(["close", issue_id, "--reason", reason])
+        .current_dir(dir)
+        .status()
+        .map_err(|e| Error::new(e.kind(), format!("Could not run bd: {}", e)))?;
+    if !status.success() {
+        return Err(Error::new(
+            ErrorKind::Other,
+            format!("bd close {} failed ({})", issue_id, status),
+        ));
+    }
+    Ok(())
+}
+
+fn git(dir: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git").arg("-C").arg(dir).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let out = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!out.is_empty()).then_some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ISSUES: &str = r#"{"id":"app-x1","title":"Fix the login redirect loop","description":"Users bounce between /login and /home.\n","status":"open","priority":1}
+{"id":"app-y2","title":"Add dark mode","status":"closed"}
+{"id":"lib-y2","title":"Dark mode for the docs"}
+not json
+"#;
+
+    #[test]
+    fn test_parse_and_match() {
+        let issues = parse_issues(ISSUES);
+        assert_eq!(issues.len(), 3);
+
+        let issue = match_issue(issues.clone(), "app-x1").unwrap();
+        assert_eq!(issue.title, "Fix the login redirect loop");
+        assert_eq!(match_issue(issues.clone(), "x1").unwrap().id, "app-x1");
+        assert_eq!(
+            match_issue(issues.clone(), "y2").unwrap_err().kind(),
+            ErrorKind::InvalidInput
+        );
+        assert_eq!(
+            match_issue(issues, "z3").unwrap_err().kind(),
+            ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn test_branch_name() {
+        let issues = parse_issues(ISSUES);
+        assert_eq!(issues[0].branch_name(), "app-x1-fix-the-login-redirect-loop");
+
+        let long = Issue {
+            id: "app-z9".to_string(),
+            title: "Support OAuth2 (Google, GitHub & GitLab) sign-in for every team".to_string(),
+            description: String::new(),
+            status: None,
+        };
+        assert_eq!(long.branch_name(), "app-z9-support-oauth2-google-github-gitlab-sign");
+
+        let symbols = Issue {
+            title: "???".to_string(),
+            ..long
+        };
+        assert_eq!(symbols.branch_name(), "app-z9");
+    }
+
+    #[test]
+    fn test_prompt() {
+        let issues = parse_issues(ISSUES);
+        assert_eq!(
+            issues[0].prompt(),
+            "Work on issue app-x1: Fix the login redirect loop\n\nUsers bounce between /login and /home."
+        );
+        assert_eq!(issues[1].prompt(), "Work on issue app-y2: Add dark mode");
+    }
+}
@@ -1,30 +1,462 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::Path;
+use std::io::{self, Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::agent::AgentKind;
+use crate::paths;
+use crate::ConfigCommands;
 
 const CONFIG_FILE: &str = ".config/wt.toml";
+const GLOBAL_CONFIG_FILE: &str = "config.toml";
 const INSTALL_MARKER: &str = ".maestro-installed";
 
-#[derive(Debug, Deserialize, Default)]
+#[derive(Debug, Serialize, Deserialize, Default)]
 pub struct Config {
+    /// Editor command, overriding `$VISUAL`/`$EDITOR`
+    pub editor: Option<String>,
+    #[serde(default)]
+    pub agent: Agent,
+    #[serde(default)]
+    pub worktree: WorktreeConfig,
     #[serde(default)]
     pub hooks: Hooks,
+    /// Untracked files copied from the main worktree into new worktrees
+    #[serde(default)]
+    pub copy: FilePatterns,
+    /// Untracked files symlinked from the main worktree into new worktrees
+    #[serde(default)]
+    pub symlink: FilePatterns,
+    /// Dependency directories seeded from the main worktree into new worktrees
+    #[serde(default)]
+    pub cache: Cache,
+    /// Port block reserved for each worktree
+    #[serde(default)]
+    pub ports: Ports,
+    /// Long-running processes (dev servers, watchers) started with the worktree
+    #[serde(default)]
+    pub processes: Vec<Process>,
+    #[serde(default)]
+    pub layout: LayoutConfig,
+    #[serde(default)]
+    pub check: Check,
+    #[serde(default)]
+    pub notify: Notify,
+    #[serde(default)]
+    pub webhook: Webhook,
+    #[serde(default)]
+    pub queue: QueueConfig,
+    #[serde(default)]
+    pub limits: Limits,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct Agent {
+    /// Which agent runs in the agent pane (default: `claude`)
+    pub kind: Option<AgentKind>,
+    /// Command for the agent pane (default: the agent's own command)
+    pub command: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct WorktreeConfig {
+    /// Where new worktrees are created, relative to the main worktree.
+    /// `{repo}` and `{branch}` are substituted (default: `../{repo}.{branch}`)
+    pub path_template: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Default)]
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct LayoutConfig {
+    /// Built-in preset name (e.g. `two-agents`) or path to a KDL template
+    pub template: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct Check {
+    /// Command run in each worktree by `wt compare` (e.g. `cargo test`)
+    pub command: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
 pub struct Hooks {
     /// One-time hook: runs only once after fresh worktree creation (e.g., `bun install`)
     pub install: Option<String>,
     /// Start hook: runs every time worktree is opened (e.g., `bun run serve`)
     pub start: Option<String>,
+    /// Runs once right after the worktree is created, before it's opened
+    pub post_create: Option<String>,
+    /// Runs before the worktree is opened; a failure aborts the open
+    pub pre_open: Option<String>,
+    /// Runs before the worktree is removed; a failure aborts the removal
+    pub pre_remove: Option<String>,
+    /// Runs in the worktree before it's merged; a failure aborts the merge
+    pub pre_merge: Option<String>,
+    /// Runs in the main worktree after a successful merge
+    pub post_merge: Option<String>,
+}
+
+/// A named process shown in its own pane of the worktree layout
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Process {
+    /// Pane title (e.g. `api`)
+    pub name: String,
+    /// Shell command to run (e.g. `bun run serve`)
+    pub command: String,
+    /// Working directory relative to the worktree root
+    pub cwd: Option<String>,
+    /// Extra environment variables for the command
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Wait for Enter before starting the command
+    #[serde(default)]
+    pub suspended: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct FilePatterns {
+    /// Glob patterns relative to the worktree root (e.g. `.env`, `certs/*.pem`)
+    #[serde(default)]
+    pub patterns: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct Cache {
+    /// Directories (or glob patterns) relative to the worktree root (e.g. `node_modules`)
+    #[serde(default)]
+    pub dirs: Vec<String>,
+    /// How to seed files when the filesystem doesn't support reflinks
+    #[serde(default)]
+    pub fallback: CacheFallback,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheFallback {
+    /// Independent copies (safe, but slow and uses disk space)
+    #[default]
+    Copy,
+    /// Hardlinks shared with the main worktree (fast, but in-place edits affect both)
+    Hardlink,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ports {
+    /// Lowest port handed out
+    #[serde(default = "default_port_base")]
+    pub base: u16,
+    /// Number of ports reserved per worktree
+    #[serde(default = "default_port_count")]
+    pub count: u16,
+}
+
+fn default_port_base() -> u16 {
+    20000
+}
+
+fn default_port_count() -> u16 {
+    5
+}
+
+impl Default for Ports {
+    fn default() -> Self {
+        Self {
+            base: default_port_base(),
+            count: default_port_count(),
+        }
+    }
+}
+
+/// Notifications when an agent starts waiting for input or permission
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Notify {
+    #[serde(default = "default_notify_enabled")]
+    pub enabled: bool,
+    /// Where notifications go
+    #[serde(default = "default_notify_backends")]
+    pub backends: Vec<NotifyBackend>,
+    /// Command for the `command` backend; gets `MAESTRO_NOTIFY_TITLE` and
+    /// `MAESTRO_NOTIFY_BODY`
+    pub command: Option<String>,
+    /// Seconds an agent has to keep waiting before it's worth a notification
+    #[serde(default = "default_quiet_period")]
+    pub quiet_period: u64,
+    /// Agents that start waiting within this many seconds of each other are
+    /// reported in one notification
+    #[serde(default = "default_batch_window")]
+    pub batch_window: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotifyBackend {
+    /// `notify-send` (`osascript` on macOS)
+    Desktop,
+    /// The configured `command`
+    Command,
+}
+
+fn default_notify_enabled() -> bool {
+    true
+}
+
+fn default_notify_backends() -> Vec<NotifyBackend> {
+    vec![NotifyBackend::Desktop]
+}
+
+fn default_quiet_period() -> u64 {
+    10
+}
+
+fn default_batch_window() -> u64 {
+    2
+}
+
+impl Default for Notify {
+    fn default() -> Self {
+        Self {
+            enabled: default_notify_enabled(),
+            backends: default_notify_backends(),
+            command: None,
+            quiet_period: default_quiet_period(),
+            batch_window: default_batch_window(),
+        }
+    }
+}
+
+/// HTTP endpoint that gets a JSON event whenever an instance changes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Webhook {
+    /// Where events are POSTed; no webhook without it
+    pub url: Option<String>,
+    /// Only send events whose new status is listed (`running`, `waiting`,
+    /// `permission` or `exited`); all of them when empty
+    #[serde(default)]
+    pub statuses: Vec<String>,
+    /// Skip status changes that end a shorter stay, in seconds
+    #[serde(default)]
+    pub min_duration: u64,
+    /// Payload with `{placeholder}`s; the event itself is sent without one
+    pub template: Option<String>,
+    /// Extra request headers (e.g. `Authorization`)
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// Retries after a failed attempt, with exponential backoff
+    #[serde(default = "default_webhook_retries")]
+    pub retries: u32,
+    /// Request timeout in seconds
+    #[serde(default = "default_webhook_timeout")]
+    pub timeout: u64,
+}
+
+fn default_webhook_retries() -> u32 {
+    3
+}
+
+fn default_webhook_timeout() -> u64 {
+    10
+}
+
+impl Default for Webhook {
+    fn default() -> Self {
+        Self {
+            url: None,
+            statuses: Vec::new(),
+            min_duration: 0,
+            template: None,
+            headers: BTreeMap::new(),
+            retries: default_webhook_retries(),
+            timeout: default_webhook_timeout(),
+        }
+    }
+}
+
+/// Dispatching of prompts queued with `maestro queue add`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QueueConfig {
+    /// Agents running in the repository above which queued prompts wait for
+    /// an idle one instead of starting a new worktree
+    #[serde(default = "default_queue_concurrency")]
+    pub concurrency: usize,
+}
+
+fn default_queue_concurrency() -> usize {
+    3
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            concurrency: default_queue_concurrency(),
+        }
+    }
+}
+
+/// Caps across all repositories, usually set in the global config
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct Limits {
+    /// Registered agents at most
+    pub max_agents: Option<usize>,
+    /// Processes (dev servers, watchers) of worktrees with an agent at most;
+    /// suspended processes don't count
+    pub max_processes: Option<usize>,
+}
+
+/// A config file contributing to the effective config
+#[derive(Debug)]
+pub struct Layer {
+    /// `global`, `repo` or `worktree`
+    pub name: &'static str,
+    pub path: PathBuf,
+}
+
+/// The config layers of a worktree merged into one table, remembering which
+/// layer each value came from
+#[derive(Debug, Default)]
+pub struct LayeredConfig {
+    pub layers: Vec<Layer>,
+    pub table: toml::Table,
+    /// Dotted key (e.g. `hooks.install`) -> layer name
+    pub origins: BTreeMap<String, &'static str>,
+}
+
+impl LayeredConfig {
+    /// Merge the global config, the main worktree's `.config/wt.toml` and the
+    /// worktree's own `.config/wt.toml`, later layers winning
+    pub fn load(worktree_path: &Path) -> io::Result<Self> {
+        Self::load_with_global(&paths::config_dir().join(GLOBAL_CONFIG_FILE), worktree_path)
+    }
+
+    /// [`LayeredConfig::load`] with the global config read from `global_path`
+    fn load_with_global(global_path: &Path, worktree_path: &Path) -> io::Result<Self> {
+        let mut candidates = vec![Layer {
+            name: "global",
+            path: global_path.to_path_buf(),
+        }];
+        if let Some(main) = main_worktree(worktree_path).filter(|main| !same_dir(main, worktree_path)) {
+            candidates.push(Layer {
+                name: "repo",
+                path: main.join(CONFIG_FILE),
+            });
+        }
+        candidates.push(Layer {
+            name: "worktree",
+            path: worktree_path.join(CONFIG_FILE),
+        });
+
+        let mut layered = Self::default();
+        for layer in candidates {
+            let content = match fs::read_to_string(&layer.path) {
+                Ok(content) => content,
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+            let table: toml::Table = toml::from_str(&content).map_err(|e| invalid_config(&layer.path, e))?;
+            merge(&mut layered.table, table, layer.name, "", &mut layered.origins);
+            layered.layers.push(layer);
+        }
+        Ok(layered)
+    }
+
+    pub fn config(&self) -> io::Result<Config> {
+        let config = toml::Value::Table(self.table.clone()).try_into().map_err(|e| {
+            let paths: Vec<String> = self.layers.iter().map(|l| l.path.display().to_string()).collect();
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Invalid config (merged from {}): {}", paths.join(", "), e),
+            )
+        })?;
+        Ok(config)
+    }
+}
+
+fn invalid_config(path: &Path, e: impl std::fmt::Display) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("Invalid config {}: {}", path.display(), e),
+    )
+}
+
+/// Deep-merge `overlay` into `base`. Tables merge key by key; any other
+/// value (including arrays) replaces what was there.
+fn merge(
+    base: &mut toml::Table,
+    overlay: toml::Table,
+    origin: &'static str,
+    prefix: &str,
+    origins: &mut BTreeMap<String, &'static str>,
+) {
+    for (key, value) in overlay {
+        let path = format!("{}{}", prefix, key);
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(existing)), toml::Value::Table(table)) => {
+                merge(existing, table, origin, &format!("{}.", path), origins);
+            }
+            (_, toml::Value::Table(table)) => {
+                let nested = format!("{}.", path);
+                origins.retain(|k, _| k != &path && !k.starts_with(&nested));
+                let mut fresh = toml::Table::new();
+                merge(&mut fresh, table, origin, &nested, origins);
+                base.insert(key, toml::Value::Table(fresh));
+            }
+            (_, value) => {
+                let nested = format!("{}.", path);
+                origins.retain(|k, _| !k.starts_with(&nested));
+                origins.insert(path, origin);
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// The main worktree of the repository containing `path`
+pub fn main_worktree(path: &Path) -> Option<PathBuf> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(path)
+        .args(["rev-parse", "--path-format=absolute", "--git-common-dir"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    // Bare repositories have no main worktree to read config from
+    let common_dir = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    if common_dir.file_name()? != ".git" {
+        return None;
+    }
+    common_dir.parent().map(Path::to_path_buf)
+}
+
+fn same_dir(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
 }
 
 impl Config {
-    /// Load config from worktree's .config/wt.toml
-    pub fn load(worktree_path: &Path) -> Option<Self> {
-        let config_path = worktree_path.join(CONFIG_FILE);
-        let content = fs::read_to_string(&config_path).ok()?;
-        toml::from_str(&content).ok()
+    /// Load the effective config for a worktree (see [`LayeredConfig::load`])
+    pub fn load(worktree_path: &Path) -> io::Result<Self> {
+        LayeredConfig::load(worktree_path)?.config()
+    }
+
+    /// Processes to start with the worktree; a legacy `hooks.start` command
+    /// runs as a process named "start" after the configured ones
+    pub fn processes(&self) -> Vec<Process> {
+        let mut processes = self.processes.clone();
+        if let Some(start) = &self.hooks.start {
+            processes.push(Process {
+                name: "start".to_string(),
+                command: start.clone(),
+                cwd: None,
+                env: BTreeMap::new(),
+                suspended: false,
+            });
+        }
+        processes
     }
 
     /// Check if install hook has already run for this worktree
@@ -38,6 +470,56 @@ impl Config {
     }
 }
 
+pub fn run(command: ConfigCommands) -> io::Result<()> {
+    match command {
+        ConfigCommands::Show => cmd_show(),
+    }
+}
+
+/// Print the effective config with the layer each value comes from
+fn cmd_show() -> io::Result<()> {
+    let layered = LayeredConfig::load(&std::env::current_dir()?)?;
+    let config = layered.config()?;
+
+    println!("Layers:");
+    for layer in &layered.layers {
+        println!("  {:<8}  {}", layer.name, layer.path.display());
+    }
+    if layered.layers.is_empty() {
+        println!("  (none, using defaults)");
+    }
+    println!();
+
+    let effective = toml::Table::try_from(&config).map_err(|e| Error::new(ErrorKind::Other, e.to_string()))?;
+    let mut values = Vec::new();
+    flatten(&effective, "", &mut values);
+
+    let rows: Vec<(String, &str)> = values
+        .into_iter()
+        .map(|(key, value)| {
+            let origin = layered.origins.get(&key).copied().unwrap_or("default");
+            (format!("{} = {}", key, value), origin)
+        })
+        .collect();
+    // Keep the origin column close by even if one value is very long
+    let width = rows.iter().map(|(row, _)| row.len()).max().unwrap_or(0).min(48);
+    for (row, origin) in rows {
+        println!("{:<width$}  # {}", row, origin, width = width);
+    }
+    Ok(())
+}
+
+/// Flatten nested tables into dotted keys; arrays are kept whole
+fn flatten(table: &toml::Table, prefix: &str, out: &mut Vec<(String, toml::Value)>) {
+    for (key, value) in table {
+        let path = format!("{}{}", prefix, key);
+        match value {
+            toml::Value::Table(nested) => flatten(nested, &format!("{}.", path), out),
+            value => out.push((path, value.clone())),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -52,6 +534,25 @@ start = "bun run serve"
         let config: Config = toml::from_str(toml).unwrap();
         assert_eq!(config.hooks.install, Some("bun install".to_string()));
         assert_eq!(config.hooks.start, Some("bun run serve".to_string()));
+        assert!(config.hooks.pre_remove.is_none());
+    }
+
+    #[test]
+    fn test_parse_lifecycle_hooks() {
+        let toml = r#"
+[hooks]
+post_create = "createdb app_$MAESTRO_BRANCH"
+pre_open = "docker compose up -d"
+pre_remove = "dropdb app_$MAESTRO_BRANCH"
+pre_merge = "cargo test"
+post_merge = "notify-send merged"
+"#;
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(config.hooks.post_create.as_deref(), Some("createdb app_$MAESTRO_BRANCH"));
+        assert_eq!(config.hooks.pre_open.as_deref(), Some("docker compose up -d"));
+        assert_eq!(config.hooks.pre_remove.as_deref(), Some("dropdb app_$MAESTRO_BRANCH"));
+        assert_eq!(config.hooks.pre_merge.as_deref(), Some("cargo test"));
+        assert_eq!(config.hooks.post_merge.as_deref(), Some("notify-send merged"));
     }
 
     #[test]
@@ -59,5 +560,170 @@ start = "bun run serve"
         let config: Config = toml::from_str("").unwrap();
         assert!(config.hooks.install.is_none());
         assert!(config.hooks.start.is_none());
+        assert!(config.copy.patterns.is_empty());
+        assert!(config.symlink.patterns.is_empty());
+    }
+
+    #[test]
+    fn test_parse_file_patterns() {
+        let toml = r#"
+[copy]
+patterns = [".env", "certs/*.pem"]
+
+[symlink]
+patterns = [".env.local"]
+"#;
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(config.copy.patterns, vec![".env", "certs/*.pem"]);
+        assert_eq!(config.symlink.patterns, vec![".env.local"]);
+    }
+
+    #[test]
+    fn test_parse_cache() {
+        let toml = r#"
+[cache]
+dirs = ["node_modules", "target"]
+fallback = "hardlink"
+"#;
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(config.cache.dirs, vec!["node_modules", "target"]);
+        assert_eq!(config.cache.fallback, CacheFallback::Hardlink);
+
+        let config: Config = toml::from_str("").unwrap();
+        assert_eq!(config.cache.fallback, CacheFallback::Copy);
+    }
+
+    #[test]
+    fn test_parse_ports() {
+        let config: Config = toml::from_str("[ports]\ncount = 3\n").unwrap();
+        assert_eq!(config.ports.base, 20000);
+        assert_eq!(config.ports.count, 3);
+    }
+
+    #[test]
+    fn test_parse_processes() {
+        let toml = r#"
+[hooks]
+start = "make watch"
+
+[[processes]]
+name = "api"
+command = "cargo run"
+cwd = "server"
+env = { RUST_LOG = "debug" }
+
+[[processes]]
+name = "tests"
+command = "cargo watch -x test"
+suspended = true
+"#;
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(config.processes.len(), 2);
+        assert_eq!(config.processes[0].cwd.as_deref(), Some("server"));
+        assert_eq!(config.processes[0].env.get("RUST_LOG").map(String::as_str), Some("debug"));
+        assert!(!config.processes[0].suspended);
+        assert!(config.processes[1].suspended);
+
+        let names: Vec<String> = config.processes().into_iter().map(|p| p.name).collect();
+        assert_eq!(names, vec!["api", "tests", "start"]);
+    }
+
+    #[test]
+    fn test_parse_layout() {
+        let config: Config = toml::from_str("[layout]\ntemplate = \"two-agents\"\n").unwrap();
+        assert_eq!(config.layout.template.as_deref(), Some("two-agents"));
+        assert!(Config::default().layout.template.is_none());
+    }
+
+    #[test]
+    fn test_parse_notify() {
+        let config: Config =
+            toml::from_str("[notify]\nbackends = [\"desktop\", \"command\"]\nquiet_period = 30\n").unwrap();
+        assert_eq!(config.notify.backends, vec![NotifyBackend::Desktop, NotifyBackend::Command]);
+        assert_eq!(config.notify.quiet_period, 30);
+        assert_eq!(config.notify.batch_window, 2);
+        assert!(config.notify.enabled);
+
+        let config: Config = toml::from_str("").unwrap();
+        assert_eq!(config.notify.backends, vec![NotifyBackend::Desktop]);
+        assert!(toml::from_str::<Config>("[notify]\nbackends = [\"bell\"]\n").is_err());
+    }
+
+    #[test]
+    fn test_merge_layers() {
+        let global: toml::Table = toml::from_str(
+            r#"
+editor = "nvim"
+[agent]
+command = "claude --model opus"
+[hooks]
+install = "make deps"
+post_merge = "git push"
+[[processes]]
+name = "global"
+command = "true"
+"#,
+        )
+        .unwrap();
+        let repo: toml::Table = toml::from_str(
+            r#"
+[hooks]
+install = "bun install"
+[ports]
+count = 3
+[[processes]]
+name = "api"
+command = "bun run serve"
+"#,
+        )
+        .unwrap();
+        let worktree: toml::Table = toml::from_str("[ports]\nbase = 30000\n").unwrap();
+
+        let mut table = toml::Table::new();
+        let mut origins = BTreeMap::new();
+        merge(&mut table, global, "global", "", &mut origins);
+        merge(&mut table, repo, "repo", "", &mut origins);
+        merge(&mut table, worktree, "worktree", "", &mut origins);
+
+        let config: Config = toml::Value::Table(table).try_into().unwrap();
+        assert_eq!(config.editor.as_deref(), Some("nvim"));
+        assert_eq!(config.agent.command.as_deref(), Some("claude --model opus"));
+        assert_eq!(config.hooks.install.as_deref(), Some("bun install"));
+        assert_eq!(config.hooks.post_merge.as_deref(), Some("git push"));
+        assert_eq!((config.ports.base, config.ports.count), (30000, 3));
+        // Arrays are replaced, not concatenated
+        assert_eq!(config.processes.len(), 1);
+        assert_eq!(config.processes[0].name, "api");
+
+        assert_eq!(origins.get("editor"), Some(&"global"));
+        assert_eq!(origins.get("hooks.install"), Some(&"repo"));
+        assert_eq!(origins.get("hooks.post_merge"), Some(&"global"));
+        assert_eq!(origins.get("ports.base"), Some(&"worktree"));
+        assert_eq!(origins.get("ports.count"), Some(&"repo"));
+        assert_eq!(origins.get("processes"), Some(&"repo"));
+    }
+
+    #[test]
+    fn test_load_reports_parse_errors() {
+        let dir = std::env::temp_dir().join(format!("maestro-config-{}", std::process::id()));
+        fs::create_dir_all(dir.join(".config")).unwrap();
+        fs::write(dir.join(CONFIG_FILE), "[hooks\ninstall = 1\n").unwrap();
+
+        // A global config that doesn't exist, so the user's own can't interfere
+        let load = || LayeredConfig::load_with_global(&dir.join(GLOBAL_CONFIG_FILE), &dir)?.config();
+        let err = load().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(err.to_string().contains(".config/wt.toml"), "{}", err);
+
+        fs::write(dir.join(CONFIG_FILE), "[hooks]\ninstall = 1\n").unwrap();
+        assert!(load().is_err());
+
+        // Errors in the global config name its file too
+        fs::write(dir.join(CONFIG_FILE), "").unwrap();
+        fs::write(dir.join(GLOBAL_CONFIG_FILE), "editor = \n").unwrap();
+        let err = load().unwrap_err();
+        assert!(err.to_string().contains(GLOBAL_CONFIG_FILE), "{}", err);
+
+        fs::remove_dir_all(&dir).unwrap();
     }
 }
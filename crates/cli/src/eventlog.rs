@@ -0,0 +1,411 @@
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::instance::{unix_now, Instance, Status};
+use crate::paths;
+use crate::transcript::TokenUsage;
+
+const LOG_FILE: &str = "events.jsonl";
+/// Size at which the log is rotated
+const MAX_LOG_SIZE: u64 = 10 * 1024 * 1024;
+/// Rotated logs kept next to the current one (`events.jsonl.1` is the newest)
+const KEEP_ROTATED: u32 = 4;
+/// How often `--follow` checks for new events
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(500);
+
+/// One line of the event log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogEntry {
+    /// Unix timestamp
+    pub timestamp: u64,
+    /// Hook event (e.g. `PreToolUse`), or what maestro did for agents
+    /// without hooks (`register`, `status`, `unregister`)
+    pub event: String,
+    pub pane_id: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// Folder the instance runs in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repo: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    /// Status after the event; `None` once the instance is gone
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<Status>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool: Option<String>,
+    /// Session totals so far, from the transcript
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tokens: Option<TokenUsage>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost_usd: Option<f64>,
+}
+
+impl LogEntry {
+    pub fn new(event: &str, pane_id: u32, instance: Option<&Instance>) -> Self {
+        let transcript = instance.and_then(|i| i.transcript.as_ref());
+        LogEntry {
+            timestamp: unix_now(),
+            event: event.to_string(),
+            pane_id,
+            session_id: instance.and_then(|i| i.session_id.clone()),
+            instance: instance.map(|i| i.folder.clone()),
+            repo: instance.and_then(|i| i.repo.clone()),
+            branch: instance.and_then(|i| i.branch.clone()),
+            cwd: instance.and_then(|i| i.cwd.clone()),
+            status: instance.map(|i| i.status),
+            tool: None,
+            tokens: transcript.map(|t| t.tokens),
+            cost_usd: transcript.map(|t| t.cost_usd),
+        }
+    }
+}
+
+pub struct EventLog {
+    dir: PathBuf,
+}
+
+impl EventLog {
+    pub fn new() -> Self {
+        Self {
+            dir: paths::state_dir(),
+        }
+    }
+
+    fn path(&self) -> PathBuf {
+        self.dir.join(LOG_FILE)
+    }
+
+    fn rotated_path(&self, n: u32) -> PathBuf {
+        self.dir.join(format!("{}.{}", LOG_FILE, n))
+    }
+
+    pub fn append(&self, entry: &LogEntry) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        self.rotate_if_full()?;
+
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        // One write per line so concurrent hooks don't interleave
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.path())?
+            .write_all(line.as_bytes())
+    }
+
+    fn rotate_if_full(&self) -> io::Result<()> {
+        let full = fs::metadata(self.path()).is_ok_and(|m| m.len() >= MAX_LOG_SIZE);
+        if !full {
+            return Ok(());
+        }
+        for n in (1..KEEP_ROTATED).rev() {
+            let from = self.rotated_path(n);
+            if from.exists() {
+                fs::rename(&from, self.rotated_path(n + 1))?;
+            }
+        }
+        fs::rename(self.path(), self.rotated_path(1))
+    }
+
+    /// All entries, oldest first, including rotated logs
+    pub fn read_all(&self) -> Vec<LogEntry> {
+        let mut paths: Vec<PathBuf> = (1..=KEEP_ROTATED).rev().map(|n| self.rotated_path(n)).collect();
+        paths.push(self.path());
+
+        paths
+            .iter()
+            .filter_map(|path| fs::read_to_string(path).ok())
+            .flat_map(|content| parse_lines(&content))
+            .collect()
+    }
+}
+
+fn parse_lines(content: &str) -> Vec<LogEntry> {
+    content
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+/// Append an entry, warning instead of failing the hook that caused it
+pub fn record(entry: &LogEntry) {
+    if let Err(e) = EventLog::new().append(entry) {
+        eprintln!("Warning: Could not write event log: {}", e);
+    }
+}
+
+/// Which entries `maestro log` shows
+#[derive(Debug, Default)]
+pub struct Filter {
+    /// Folder name or pane id
+    pub instance: Option<String>,
+    pub repo: Option<String>,
+    /// Unix timestamps bounding the entries
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+}
+
+impl Filter {
+    pub fn matches(&self, entry: &LogEntry) -> bool {
+        if let Some(instance) = &self.instance
+            && entry.instance.as_ref() != Some(instance)
+            && entry.pane_id.to_string() != *instance
+        {
+            return false;
+        }
+        if let Some(repo) = &self.repo
+            && entry.repo.as_ref() != Some(repo)
+        {
+            return false;
+        }
+        self.since.is_none_or(|since| entry.timestamp >= since)
+            && self.until.is_none_or(|until| entry.timestamp <= until)
+    }
+}
+
+pub struct LogOptions {
+    pub filter: Filter,
+    /// Only the last N matching entries
+    pub limit: Option<usize>,
+    pub follow: bool,
+    pub json: bool,
+}
+
+pub fn cmd_log(opts: &LogOptions) -> io::Result<()> {
+    let log = EventLog::new();
+    let entries: Vec<LogEntry> = log
+        .read_all()
+        .into_iter()
+        .filter(|e| opts.filter.matches(e))
+        .collect();
+    let skip = opts
+        .limit
+        .map(|limit| entries.len().saturating_sub(limit))
+        .unwrap_or(0);
+    for entry in &entries[skip..] {
+        print_entry(entry, opts.json)?;
+    }
+
+    if opts.follow {
+        follow(&log, opts)?;
+    }
+    Ok(())
+}
+
+/// Print entries as they're appended, until interrupted
+fn follow(log: &EventLog, opts: &LogOptions) -> io::Result<()> {
+    let path = log.path();
+    let mut offset = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+    let mut partial = String::new();
+
+    loop {
+        std::thread::sleep(FOLLOW_INTERVAL);
+
+        let len = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        if len < offset {
+            // Rotated: the new file starts from scratch
+            offset = 0;
+            partial.clear();
+        }
+        if len == offset {
+            continue;
+        }
+
+        let mut file = File::open(&path)?;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut chunk = String::new();
+        offset += file.read_to_string(&mut chunk)? as u64;
+        partial.push_str(&chunk);
+
+        // Keep an unfinished last line for the next round
+        let complete = match partial.rfind('\n') {
+            Some(end) => partial.drain(..=end).collect::<String>(),
+            None => continue,
+        };
+        for entry in parse_lines(&complete) {
+            if opts.filter.matches(&entry) {
+                print_entry(&entry, opts.json)?;
+            }
+        }
+    }
+}
+
+fn print_entry(entry: &LogEntry, json: bool) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    if json {
+        writeln!(stdout, "{}", serde_json::to_string(entry)?)?;
+    } else {
+        let instance = entry.instance.as_deref().unwrap_or("?");
+        let mut line = format!(
+            "{}  {} (pane {})  {}",
+            format_timestamp(entry.timestamp),
+            instance,
+            entry.pane_id,
+            entry.event
+        );
+        match entry.status {
+            Some(status) => line.push_str(&format!("  {}", status)),
+            None => line.push_str("  exited"),
+        }
+        if let Some(tool) = &entry.tool {
+            line.push_str(&format!("  {}", tool));
+        }
+        writeln!(stdout, "{}", line)?;
+    }
+    stdout.flush()
+}
+
+/// Parse a duration like `90s`, `15m`, `2h`, `1d` or `1w` into seconds
+pub fn parse_duration(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("Invalid duration '{}': expected e.g. 30m, 2h or 1d", s))?;
+    let unit = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(format!("Invalid duration unit in '{}': use s, m, h, d or w", s)),
+    };
+    number
+        .checked_mul(unit)
+        .ok_or_else(|| format!("Invalid duration '{}': too long", s))
+}
+
+/// Unix timestamp `ago` seconds before now, for `--since`/`--until`
+pub fn time_ago(ago: u64) -> u64 {
+    unix_now().saturating_sub(ago)
+}
+
+/// Format a Unix timestamp as UTC, e.g. `2024-05-01 13:45:00Z`
+pub fn format_timestamp(timestamp: u64) -> String {
+    let days = timestamp / 86400;
+    let secs = timestamp % 86400;
+    let (year, month, day) = civil_from_days(days as i64);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+/// Days since 1970-01-01 to a (year, month, day) date, after Howard Hinnant's
+/// `civil_from_days`
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(timestamp: u64, instance: &str, repo: &str) -> LogEntry {
+        LogEntry {
+            timestamp,
+            event: "PreToolUse".to_string(),
+            pane_id: 7,
+            session_id: None,
+            instance: Some(instance.to_string()),
+            repo: Some(repo.to_string()),
+            branch: None,
+            cwd: None,
+            status: Some(Status::Running),
+            tool: Some("Bash".to_string()),
+            tokens: None,
+            cost_usd: None,
+        }
+    }
+
+    fn temp_log(name: &str) -> EventLog {
+        let dir = std::env::temp_dir().join(format!("maestro-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        EventLog { dir }
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90"), Ok(90));
+        assert_eq!(parse_duration("15m"), Ok(900));
+        assert_eq!(parse_duration("2h"), Ok(7200));
+        assert_eq!(parse_duration("1d"), Ok(86400));
+        assert_eq!(parse_duration("1w"), Ok(604800));
+        assert!(parse_duration("h").is_err());
+        assert!(parse_duration("3y").is_err());
+        assert!(parse_duration("99999999999999999w").is_err());
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00Z");
+        assert_eq!(format_timestamp(1_714_571_100), "2024-05-01 13:45:00Z");
+        assert_eq!(format_timestamp(951_825_600), "2000-02-29 12:00:00Z");
+    }
+
+    #[test]
+    fn test_filter() {
+        let e = entry(100, "app.fix-1", "app");
+        assert!(Filter::default().matches(&e));
+        let by_instance = |instance: &str| Filter {
+            instance: Some(instance.to_string()),
+            ..Default::default()
+        };
+        assert!(by_instance("app.fix-1").matches(&e));
+        assert!(by_instance("7").matches(&e));
+        assert!(!by_instance("app.fix-2").matches(&e));
+
+        let by_repo = Filter {
+            repo: Some("other".to_string()),
+            ..Default::default()
+        };
+        assert!(!by_repo.matches(&e));
+
+        let window = Filter {
+            since: Some(50),
+            until: Some(99),
+            ..Default::default()
+        };
+        assert!(!window.matches(&e));
+    }
+
+    #[test]
+    fn test_append_and_rotate() {
+        let log = temp_log("rotate");
+        log.append(&entry(1, "a", "r")).unwrap();
+        log.append(&entry(2, "b", "r")).unwrap();
+
+        // Pretend the log filled up
+        let file = OpenOptions::new().append(true).open(log.path()).unwrap();
+        file.set_len(MAX_LOG_SIZE).unwrap();
+        log.append(&entry(3, "c", "r")).unwrap();
+
+        assert!(log.rotated_path(1).exists());
+        let timestamps: Vec<u64> = log.read_all().iter().map(|e| e.timestamp).collect();
+        assert_eq!(timestamps, vec![1, 2, 3]);
+        fs::remove_dir_all(&log.dir).unwrap();
+    }
+}
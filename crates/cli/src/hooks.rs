@@ -0,0 +1,112 @@
+use std::io::{self, Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Describes the worktree a lifecycle hook runs for; exported to the hook
+/// as `MAESTRO_*` environment variables
+#[derive(Debug, Default)]
+pub struct HookContext {
+    pub worktree: PathBuf,
+    pub branch: Option<String>,
+    pub default_branch: Option<String>,
+    /// Path of the main worktree
+    pub repo: Option<PathBuf>,
+    /// Extra variables, e.g. the worktree's ports
+    pub env: Vec<(String, String)>,
+}
+
+impl HookContext {
+    pub fn env_vars(&self, hook: &str) -> Vec<(String, String)> {
+        let mut vars = vec![
+            ("MAESTRO_HOOK".to_string(), hook.to_string()),
+            (
+                "MAESTRO_WORKTREE".to_string(),
+                self.worktree.to_string_lossy().to_string(),
+            ),
+        ];
+        if let Some(branch) = &self.branch {
+            vars.push(("MAESTRO_BRANCH".to_string(), branch.clone()));
+        }
+        if let Some(default_branch) = &self.default_branch {
+            vars.push(("MAESTRO_DEFAULT_BRANCH".to_string(), default_branch.clone()));
+        }
+        if let Some(repo) = &self.repo {
+            vars.push(("MAESTRO_REPO".to_string(), repo.to_string_lossy().to_string()));
+        }
+        vars.extend(self.env.iter().cloned());
+        vars
+    }
+}
+
+/// Run hook `name` (if configured) through `sh -c` in `cwd`.
+///
+/// The hook's stdout is sent to stderr so it never mixes with output meant
+/// for other programs (e.g. `wt switch --print-layout`).
+pub fn run(name: &str, cmd: Option<&str>, ctx: &HookContext, cwd: &Path) -> io::Result<()> {
+    let Some(cmd) = cmd else {
+        return Ok(());
+    };
+
+    eprintln!("Running {} hook: {}", name, cmd);
+    let status = Command::new("sh")
+        .args(["-c", cmd])
+        .current_dir(cwd)
+        .envs(ctx.env_vars(name))
+        .stdin(Stdio::null())
+        .stdout(io::stderr())
+        .status()?;
+
+    if !status.success() {
+        let code = status
+            .code()
+            .map(|c| c.to_string())
+            .unwrap_or_else(|| "signal".to_string());
+        return Err(Error::new(
+            ErrorKind::Other,
+            format!("{} hook failed (exit {})", name, code),
+        ));
+    }
+    Ok(())
+}
+
+/// Run a `post_*` hook, downgrading failures to a warning
+pub fn run_post(name: &str, cmd: Option<&str>, ctx: &HookContext, cwd: &Path) {
+    if let Err(e) = run(name, cmd, ctx, cwd) {
+        eprintln!("Warning: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env_vars() {
+        let ctx = HookContext {
+            worktree: PathBuf::from("/home/user/project.feature"),
+            branch: Some("feature".to_string()),
+            default_branch: Some("main".to_string()),
+            repo: Some(PathBuf::from("/home/user/project")),
+            env: vec![("MAESTRO_PORT".to_string(), "20000".to_string())],
+        };
+        let vars = ctx.env_vars("pre_remove");
+
+        assert!(vars.contains(&("MAESTRO_HOOK".to_string(), "pre_remove".to_string())));
+        assert!(vars.contains(&("MAESTRO_WORKTREE".to_string(), "/home/user/project.feature".to_string())));
+        assert!(vars.contains(&("MAESTRO_BRANCH".to_string(), "feature".to_string())));
+        assert!(vars.contains(&("MAESTRO_DEFAULT_BRANCH".to_string(), "main".to_string())));
+        assert!(vars.contains(&("MAESTRO_REPO".to_string(), "/home/user/project".to_string())));
+        assert!(vars.contains(&("MAESTRO_PORT".to_string(), "20000".to_string())));
+    }
+
+    #[test]
+    fn test_run_reports_failure() {
+        let ctx = HookContext::default();
+        let cwd = std::env::temp_dir();
+
+        assert!(run("pre_remove", None, &ctx, &cwd).is_ok());
+        assert!(run("pre_remove", Some("true"), &ctx, &cwd).is_ok());
+        let err = run("pre_remove", Some("exit 3"), &ctx, &cwd).unwrap_err();
+        assert_eq!(err.to_string(), "pre_remove hook failed (exit 3)");
+    }
+}
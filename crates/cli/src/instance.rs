@@ -1,12 +1,50 @@
 use serde::{Deserialize, Serialize};
-use std::fs;
-use std::path::PathBuf;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::agent::AgentKind;
+use crate::tool::ToolCall;
+use crate::transcript::{self, TranscriptSummary};
+use crate::webhook::Change;
+use crate::worktree::git_context;
+
+/// Environment variable naming the batch an agent was launched in
+pub const BATCH_ENV: &str = "MAESTRO_BATCH";
+/// Tool calls kept per instance
+const RECENT_TOOLS: usize = 10;
+/// Lock file serializing changes to the store, next to it
+const LOCK_FILE: &str = "instances.lock";
+
+pub fn batch_from_env() -> Option<String> {
+    std::env::var(BATCH_ENV).ok().filter(|b| !b.is_empty())
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Status {
     Running,
     Waiting,
+    /// Blocked on a permission prompt
+    Permission,
+}
+
+impl Status {
+    pub fn icon(&self) -> &'static str {
+        match self {
+            Status::Running => "⚡",
+            Status::Waiting => "⏳",
+            Status::Permission => "🔒",
+        }
+    }
 }
 
 impl std::fmt::Display for Status {
@@ -14,6 +52,7 @@ impl std::fmt::Display for Status {
         match self {
             Status::Running => write!(f, "running"),
             Status::Waiting => write!(f, "waiting"),
+            Status::Permission => write!(f, "permission"),
         }
     }
 }
@@ -25,7 +64,8 @@ impl std::str::FromStr for Status {
         match s.to_lowercase().as_str() {
             "running" => Ok(Status::Running),
             "waiting" => Ok(Status::Waiting),
-            _ => Err(format!("Invalid status: {s}. Use 'running' or 'waiting'")),
+            "permission" => Ok(Status::Permission),
+            _ => Err(format!("Invalid status: {s}. Use 'running', 'waiting' or 'permission'")),
         }
     }
 }
@@ -35,6 +75,69 @@ pub struct Instance {
     pub pane_id: u32,
     pub folder: String,
     pub status: Status,
+    /// Full working directory the instance was registered from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    /// Repository and branch of `cwd` at registration
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repo: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    #[serde(default)]
+    pub agent: AgentKind,
+    /// Batch of parallel agents this instance belongs to (`maestro fanout`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub batch: Option<String>,
+    /// Claude Code session id, from the hook payload
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// Token usage, cost and latest messages read from the session transcript
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transcript: Option<TranscriptSummary>,
+    /// Unix timestamp of registration
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registered_at: Option<u64>,
+    /// Unix timestamp of the last status change
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status_since: Option<u64>,
+    /// Tool the agent is running, from the `PreToolUse` hook
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub current_tool: Option<ToolCall>,
+    /// Latest tool calls, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub recent_tools: Vec<ToolCall>,
+    /// Whether a notification went out for the current wait
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub notified: bool,
+}
+
+#[cfg(test)]
+impl Instance {
+    /// An instance with just the fields tests tend to care about
+    pub fn for_test(
+        folder: &str,
+        repo: Option<&str>,
+        branch: Option<&str>,
+        status: Status,
+    ) -> Self {
+        Instance {
+            pane_id: 1,
+            folder: folder.to_string(),
+            status,
+            cwd: None,
+            repo: repo.map(str::to_string),
+            branch: branch.map(str::to_string),
+            agent: AgentKind::Claude,
+            batch: None,
+            session_id: None,
+            transcript: None,
+            registered_at: None,
+            status_since: None,
+            notified: false,
+            current_tool: None,
+            recent_tools: Vec::new(),
+        }
+    }
 }
 
 pub struct InstanceStore {
@@ -55,7 +158,7 @@ impl InstanceStore {
             .unwrap_or_default()
     }
 
-    pub fn save(&self, instances: &[Instance]) -> std::io::Result<()> {
+    fn save(&self, instances: &[Instance]) -> io::Result<()> {
         if let Some(parent) = self.path.parent() {
             fs::create_dir_all(parent)?;
         }
@@ -64,38 +167,240 @@ impl InstanceStore {
         fs::write(&self.path, json)
     }
 
-    pub fn register(&self, pane_id: u32, folder: String) -> std::io::Result<()> {
+    /// Apply `change` to the stored instances while holding the store's lock,
+    /// so concurrent hooks don't overwrite each other's changes
+    pub fn update<T>(&self, change: impl FnOnce(&mut Vec<Instance>) -> T) -> io::Result<T> {
+        let _lock = self.lock()?;
         let mut instances = self.load();
+        let result = change(&mut instances);
+        self.save(&instances)?;
+        Ok(result)
+    }
 
-        // Remove existing entry with same pane_id
-        instances.retain(|i| i.pane_id != pane_id);
+    /// Exclusive lock on the store, released when the file is dropped
+    fn lock(&self) -> io::Result<File> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.path.with_file_name(LOCK_FILE))?;
+        file.lock()?;
+        Ok(file)
+    }
 
-        // Add new instance
-        instances.push(Instance {
+    pub fn register(
+        &self,
+        pane_id: u32,
+        folder: String,
+        cwd: String,
+        agent: AgentKind,
+        batch: Option<String>,
+    ) -> io::Result<Instance> {
+        let now = unix_now();
+        let (repo, branch) = git_context(Path::new(&cwd));
+        let instance = Instance {
             pane_id,
             folder,
             status: Status::Running,
-        });
+            cwd: Some(cwd),
+            repo,
+            branch,
+            agent,
+            batch,
+            session_id: None,
+            transcript: None,
+            registered_at: Some(now),
+            status_since: Some(now),
+            notified: false,
+            current_tool: None,
+            recent_tools: Vec::new(),
+        };
 
-        self.save(&instances)
+        self.update(|instances| {
+            // Replace any existing entry with the same pane_id
+            instances.retain(|i| i.pane_id != pane_id);
+            instances.push(instance.clone());
+        })?;
+        Ok(instance)
     }
 
-    pub fn update_status(&self, pane_id: u32, status: Status) -> std::io::Result<()> {
-        let mut instances = self.load();
+    /// The instance in `pane_id`, if it's registered
+    pub fn get(&self, pane_id: u32) -> Option<Instance> {
+        self.load().into_iter().find(|i| i.pane_id == pane_id)
+    }
+
+    /// Apply what a hook reported about an instance in one write. Returns the
+    /// status change if there was one, which starts a new wait for
+    /// notifications.
+    pub fn update_instance(
+        &self,
+        pane_id: u32,
+        update: InstanceUpdate,
+    ) -> io::Result<Option<Change>> {
+        self.update(|instances| {
+            let instance = instances.iter_mut().find(|i| i.pane_id == pane_id)?;
+            update.apply(instance)
+        })
+    }
 
-        for instance in &mut instances {
-            if instance.pane_id == pane_id {
-                instance.status = status;
-                break;
+    /// Set an instance's status, returning the change if there was one
+    pub fn update_status(&self, pane_id: u32, status: Status) -> io::Result<Option<Change>> {
+        self.update_instance(
+            pane_id,
+            InstanceUpdate {
+                status: Some(status),
+                ..InstanceUpdate::default()
+            },
+        )
+    }
+
+    /// Unregister the instance in `pane_id`, returning it if it was registered
+    pub fn unregister(&self, pane_id: u32) -> io::Result<Vec<Instance>> {
+        let removed = self.update(|instances| {
+            let (removed, kept) = std::mem::take(instances)
+                .into_iter()
+                .partition::<Vec<_>, _>(|i| i.pane_id == pane_id);
+            *instances = kept;
+            removed
+        })?;
+        forget_transcripts(&removed);
+        Ok(removed)
+    }
+
+    /// Unregister all instances running inside `dir`, returning them
+    pub fn unregister_within(&self, dir: &Path) -> io::Result<Vec<Instance>> {
+        let removed = self.update(|instances| {
+            let (removed, kept) = std::mem::take(instances).into_iter().partition::<Vec<_>, _>(
+                |i| i.cwd.as_deref().is_some_and(|cwd| Path::new(cwd).starts_with(dir)),
+            );
+            *instances = kept;
+            removed
+        })?;
+        forget_transcripts(&removed);
+        Ok(removed)
+    }
+}
+
+/// What a hook reports about an instance; unset fields are left alone
+#[derive(Debug, Default)]
+pub struct InstanceUpdate {
+    pub session_id: Option<String>,
+    pub transcript: Option<TranscriptSummary>,
+    /// The tool now running, or `Some(None)` once it's done. Started tools
+    /// are added to the instance's recent tools.
+    pub tool: Option<Option<ToolCall>>,
+    pub status: Option<Status>,
+}
+
+impl InstanceUpdate {
+    /// Apply the update, returning the status change if there was one
+    fn apply(self, instance: &mut Instance) -> Option<Change> {
+        if self.session_id.is_some() {
+            instance.session_id = self.session_id;
+        }
+        if self.transcript.is_some() {
+            instance.transcript = self.transcript;
+        }
+        if let Some(tool) = self.tool {
+            if let Some(tool) = &tool {
+                instance.recent_tools.push(tool.clone());
+                let excess = instance.recent_tools.len().saturating_sub(RECENT_TOOLS);
+                instance.recent_tools.drain(..excess);
             }
+            instance.current_tool = tool;
         }
 
-        self.save(&instances)
+        let status = self.status.filter(|&status| status != instance.status)?;
+        let old = instance.status;
+        let since = instance.status_since;
+        instance.status = status;
+        instance.status_since = Some(unix_now());
+        instance.notified = false;
+        Some(Change::StatusChanged {
+            instance: instance.clone(),
+            old,
+            since,
+        })
     }
+}
 
-    pub fn unregister(&self, pane_id: u32) -> std::io::Result<()> {
-        let mut instances = self.load();
-        instances.retain(|i| i.pane_id != pane_id);
-        self.save(&instances)
+/// Sessions of unregistered instances won't be summarized again
+fn forget_transcripts(removed: &[Instance]) {
+    for session_id in removed.iter().filter_map(|i| i.session_id.as_deref()) {
+        transcript::forget(session_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_is_serialized() {
+        let dir = std::env::temp_dir().join(format!("maestro-instances-{}", std::process::id()));
+        let store = InstanceStore {
+            path: dir.join("instances.json"),
+        };
+        std::thread::scope(|scope| {
+            for pane_id in 0..8 {
+                let store = &store;
+                scope.spawn(move || {
+                    store
+                        .update(|instances| {
+                            instances.push(Instance {
+                                pane_id,
+                                ..Instance::for_test("app", None, None, Status::Running)
+                            })
+                        })
+                        .unwrap()
+                });
+            }
+        });
+        assert_eq!(store.load().len(), 8);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_instance_update() {
+        let mut instance = Instance::for_test("app", None, None, Status::Running);
+        let tool = ToolCall::new("Bash", None, None, 100);
+        let update = InstanceUpdate {
+            session_id: Some("s1".to_string()),
+            tool: Some(Some(tool.clone())),
+            status: Some(Status::Running),
+            ..InstanceUpdate::default()
+        };
+        assert!(update.apply(&mut instance).is_none());
+        assert_eq!(instance.session_id.as_deref(), Some("s1"));
+        assert_eq!(instance.current_tool.as_ref(), Some(&tool));
+        assert_eq!(instance.recent_tools.len(), 1);
+
+        // A permission prompt leaves the tool alone
+        let update = InstanceUpdate {
+            status: Some(Status::Permission),
+            ..InstanceUpdate::default()
+        };
+        let change = update.apply(&mut instance);
+        assert!(matches!(
+            change,
+            Some(Change::StatusChanged {
+                old: Status::Running,
+                ..
+            })
+        ));
+        assert_eq!(instance.status, Status::Permission);
+        assert_eq!(instance.current_tool.as_ref(), Some(&tool));
+        assert_eq!(instance.session_id.as_deref(), Some("s1"));
+
+        let update = InstanceUpdate {
+            tool: Some(None),
+            ..InstanceUpdate::default()
+        };
+        assert!(update.apply(&mut instance).is_none());
+        assert_eq!(instance.current_tool, None);
+        assert_eq!(instance.recent_tools.len(), 1);
     }
 }
@@ -1,100 +1,303 @@
-use std::io;
-use std::path::Path;
+use kdl::{KdlDocument, KdlEntry, KdlError, KdlNode, KdlValue};
+use std::io::{self, Error, ErrorKind};
+use std::path::{Path, PathBuf};
 
-/// Generate a Zellij KDL layout for a worktree
+use crate::config::Process;
+
+/// Built-in layout templates, selectable by name with `[layout] template`
+const PRESETS: &[(&str, &str)] = &[
+    ("default", include_str!("layouts/default.kdl")),
+    ("agent-only", include_str!("layouts/agent-only.kdl")),
+    ("editor-agent", include_str!("layouts/editor-agent.kdl")),
+    ("two-agents", include_str!("layouts/two-agents.kdl")),
+];
+
+/// Resolve a layout template: a preset name, or a path to a KDL file
+/// (relative paths are resolved against the worktree root).
+/// `None` selects the default preset.
+pub fn load_template(spec: Option<&str>, worktree_path: &Path) -> io::Result<String> {
+    let spec = spec.unwrap_or("default");
+    if let Some((_, template)) = PRESETS.iter().find(|(name, _)| *name == spec) {
+        return Ok(template.to_string());
+    }
+
+    let path = worktree_path.join(spec);
+    std::fs::read_to_string(&path).map_err(|e| {
+        let presets: Vec<&str> = PRESETS.iter().map(|(name, _)| *name).collect();
+        Error::new(
+            e.kind(),
+            format!(
+                "Layout template '{}' is neither a preset ({}) nor a readable file: {}",
+                spec,
+                presets.join(", "),
+                e
+            ),
+        )
+    })
+}
+
+/// Render a Zellij KDL layout for a worktree from `template`
 ///
-/// Layout structure:
-/// - Left pane (60%): Text editor opening the worktree root
-/// - Bottom-left (stacked): Terminal for running commands
-/// - Right pane (40%): Claude Code instance
+/// Placeholders:
+/// - `{cwd}`, `{tab_name}`: string contents (use inside quotes)
+/// - `{editor}`: pane body running the editor on the worktree root
+/// - `{agent}`: pane body running the agent (Claude Code by default)
+/// - `{processes}`: one child pane per process, titled with its name
 pub fn generate_layout(
+    template: &str,
     worktree_path: &Path,
     editor_cmd: &str,
+    agent: &[String],
     install_cmd: Option<&str>,
-    start_cmd: Option<&str>,
+    processes: &[Process],
+    env: &[(String, String)],
 ) -> String {
     let path_str = worktree_path.to_string_lossy();
 
-    // Build the command sequence for the terminal pane
-    let terminal_cmd = build_terminal_command(install_cmd, start_cmd);
-
-    format!(
-        r#"layout {{
-    default_tab_template {{
-        pane size=1 borderless=true {{
-            plugin location="zellij:tab-bar"
-        }}
-        children
-        pane size=2 borderless=true {{
-            plugin location="zellij:status-bar"
-        }}
-    }}
-
-    tab name="{tab_name}" cwd="{cwd}" {{
-        pane split_direction="vertical" {{
-            pane split_direction="horizontal" size="60%" {{
-                pane {{
-                    command "{editor}"
-                    args "{cwd}"
-                }}
-                pane size="30%" {{
-                    {terminal_pane}
-                }}
-            }}
-            pane size="40%" {{
-                command "claude"
-            }}
-        }}
-    }}
-}}
-"#,
-        tab_name = worktree_path
-            .file_name()
-            .map(|n| n.to_string_lossy())
-            .unwrap_or_else(|| "worktree".into()),
-        cwd = path_str,
-        editor = editor_cmd,
-        terminal_pane = terminal_cmd,
+    render_template(
+        template,
+        &[
+            ("tab_name", escape_string(&tab_name(worktree_path))),
+            ("cwd", escape_string(&path_str)),
+            ("editor", render_nodes(&command_nodes(editor_cmd, Some(&path_str)))),
+            ("agent", render_nodes(&argv_nodes(agent.to_vec()))),
+            (
+                "processes",
+                render_nodes(&process_panes(worktree_path, install_cmd, processes, env)),
+            ),
+        ],
     )
 }
 
-fn build_terminal_command(install_cmd: Option<&str>, start_cmd: Option<&str>) -> String {
-    match (install_cmd, start_cmd) {
-        (Some(install), Some(start)) => {
-            // Run install first, then start
-            format!(
-                r#"command "bash"
-                    args "-c" "{} && {}"
-                    start_suspended false"#,
-                escape_shell(install),
-                escape_shell(start)
-            )
-        }
-        (Some(install), None) => {
-            format!(
-                r#"command "bash"
-                    args "-c" "{}"
-                    start_suspended false"#,
-                escape_shell(install)
-            )
-        }
-        (None, Some(start)) => {
-            format!(
-                r#"command "bash"
-                    args "-c" "{}"
-                    start_suspended false"#,
-                escape_shell(start)
-            )
+/// Builder-style construction of layout nodes
+trait NodeBuilder {
+    fn arg(self, value: impl Into<KdlValue>) -> Self;
+    fn prop(self, key: &str, value: impl Into<KdlValue>) -> Self;
+    fn child(self, node: KdlNode) -> Self;
+}
+
+impl NodeBuilder for KdlNode {
+    fn arg(mut self, value: impl Into<KdlValue>) -> Self {
+        self.push(KdlEntry::new(value));
+        self
+    }
+
+    fn prop(mut self, key: &str, value: impl Into<KdlValue>) -> Self {
+        self.push(KdlEntry::new_prop(key, value));
+        self
+    }
+
+    fn child(mut self, node: KdlNode) -> Self {
+        self.ensure_children().nodes_mut().push(node);
+        self
+    }
+}
+
+/// Render nodes as a formatted KDL document
+fn to_kdl(nodes: impl IntoIterator<Item = KdlNode>) -> String {
+    let mut doc = KdlDocument::new();
+    doc.nodes_mut().extend(nodes);
+    doc.fmt();
+    doc.to_string()
+}
+
+/// Render nodes for substitution into a template, without the trailing newline
+fn render_nodes(nodes: &[KdlNode]) -> String {
+    to_kdl(nodes.iter().cloned()).trim_end().to_string()
+}
+
+/// Escape `s` for use inside a quoted KDL string
+fn escape_string(s: &str) -> String {
+    let quoted = KdlValue::String(s.to_string()).to_string();
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+/// Parse a rendered layout, to catch template mistakes before Zellij does
+pub fn parse_layout(layout: &str) -> io::Result<KdlDocument> {
+    layout.parse().map_err(|e: KdlError| {
+        let offset = e.span.offset().min(layout.len());
+        let line = layout.as_bytes()[..offset].iter().filter(|&&b| b == b'\n').count() + 1;
+        let message = match e.help {
+            Some(help) => format!("{} {}", e, help),
+            None => e.to_string(),
+        };
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("Invalid KDL (line {}: {})", line, message),
+        )
+    })
+}
+
+/// Substitute `{name}` placeholders, indenting multi-line values to the
+/// placeholder's line. Substituted values are not scanned again.
+fn render_template(template: &str, vars: &[(&str, String)]) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    for line in template.lines() {
+        let indent = &line[..line.len() - line.trim_start().len()];
+        let mut rest = line;
+        while let Some(pos) = rest.find('{') {
+            rendered.push_str(&rest[..pos]);
+            let after = &rest[pos + 1..];
+            let var = vars.iter().find(|(name, _)| {
+                after.starts_with(name) && after[name.len()..].starts_with('}')
+            });
+            match var {
+                Some((name, value)) => {
+                    rendered.push_str(&value.replace('\n', &format!("\n{}", indent)));
+                    rest = &after[name.len() + 1..];
+                }
+                None => {
+                    rendered.push('{');
+                    rest = after;
+                }
+            }
         }
-        (None, None) => {
-            // Just a regular shell
-            String::new()
+        rendered.push_str(rest);
+        rendered.push('\n');
+    }
+    rendered
+}
+
+/// Merge rendered single-worktree layouts into one layout with a tab per
+/// worktree, keeping the first layout's tab template
+pub fn combine_layouts(layouts: &[String]) -> io::Result<String> {
+    let mut combined: Option<KdlNode> = None;
+    for layout in layouts {
+        let doc = parse_layout(layout)?;
+        let root = doc
+            .nodes()
+            .iter()
+            .find(|node| node.name().value() == "layout")
+            .cloned()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Layout has no `layout` node"))?;
+
+        match &mut combined {
+            None => combined = Some(root),
+            Some(combined) => {
+                let tabs = root
+                    .children()
+                    .into_iter()
+                    .flat_map(|children| children.nodes())
+                    .filter(|node| node.name().value() == "tab")
+                    .cloned();
+                combined.ensure_children().nodes_mut().extend(tabs);
+            }
         }
     }
+
+    combined
+        .map(|root| to_kdl([root]))
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "No layouts to combine"))
+}
+
+/// Name of the Zellij tab opened for a worktree
+pub fn tab_name(worktree_path: &Path) -> String {
+    worktree_path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| "worktree".to_string())
+}
+
+/// Pane body running `cmd`, split like a shell would so `code --wait`
+/// becomes `command` + `args`, with `extra_arg` appended
+fn command_nodes(cmd: &str, extra_arg: Option<&str>) -> Vec<KdlNode> {
+    let mut words = shlex::split(cmd)
+        .filter(|words| !words.is_empty())
+        .unwrap_or_else(|| vec![cmd.to_string()]);
+    words.extend(extra_arg.map(str::to_string));
+    argv_nodes(words)
+}
+
+/// Pane body running `argv[0]` with the remaining words as `args`
+fn argv_nodes(mut argv: Vec<String>) -> Vec<KdlNode> {
+    let command = argv.remove(0);
+    let mut nodes = vec![KdlNode::new("command").arg(command)];
+    if !argv.is_empty() {
+        nodes.push(argv.into_iter().fold(KdlNode::new("args"), |args, word| args.arg(word)));
+    }
+    nodes
 }
 
-fn escape_shell(cmd: &str) -> String {
-    cmd.replace('\\', "\\\\").replace('"', "\\\"")
+/// File the install pane creates once the install hook succeeded, so the
+/// processes know they can start. Kept outside the worktree so it doesn't
+/// show up in `git status`.
+pub fn install_done_marker(worktree_path: &Path) -> PathBuf {
+    let name: String = worktree_path
+        .to_string_lossy()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    std::env::temp_dir()
+        .join("maestro-ai")
+        .join(format!("installed{}", name))
+}
+
+/// One pane per process. A pending install gets a pane of its own at the
+/// worktree root, and every process waits for it to succeed; with neither, a
+/// plain shell.
+fn process_panes(
+    worktree_path: &Path,
+    install_cmd: Option<&str>,
+    processes: &[Process],
+    env: &[(String, String)],
+) -> Vec<KdlNode> {
+    let marker = install_done_marker(worktree_path);
+    let marker = shlex::try_quote(&marker.to_string_lossy())
+        .map(|m| m.to_string())
+        .unwrap_or_default();
+    let install = install_cmd.map(|install| {
+        let script = format!("{} && touch {}", install, marker);
+        command_pane("install", &script, Some(worktree_path), false, env)
+    });
+    if processes.is_empty() {
+        return vec![install.unwrap_or_else(|| KdlNode::new("pane"))];
+    }
+
+    let panes = processes.iter().map(|process| {
+        let script = match install_cmd {
+            Some(_) => format!(
+                "until [ -e {} ]; do sleep 1; done; {}",
+                marker, process.command
+            ),
+            None => process.command.clone(),
+        };
+        let cwd = process.cwd.as_ref().map(|dir| worktree_path.join(dir));
+        let env: Vec<(String, String)> = env
+            .iter()
+            .cloned()
+            .chain(process.env.iter().map(|(k, v)| (k.clone(), v.clone())))
+            .collect();
+        command_pane(&process.name, &script, cwd.as_deref(), process.suspended, &env)
+    });
+    install.into_iter().chain(panes).collect()
+}
+
+fn command_pane(
+    name: &str,
+    script: &str,
+    cwd: Option<&Path>,
+    suspended: bool,
+    env: &[(String, String)],
+) -> KdlNode {
+    // Export env vars (e.g. the worktree's ports) for the command
+    let script = if env.is_empty() {
+        script.to_string()
+    } else {
+        let assignments: Vec<String> = env
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, shlex::try_quote(v).unwrap_or("''".into())))
+            .collect();
+        format!("export {}; {}", assignments.join(" "), script)
+    };
+
+    let mut pane = KdlNode::new("pane")
+        .prop("name", name)
+        .child(KdlNode::new("command").arg("bash"))
+        .child(KdlNode::new("args").arg("-c").arg(script));
+    if let Some(cwd) = cwd {
+        pane = pane.child(KdlNode::new("cwd").arg(cwd.to_string_lossy().to_string()));
+    }
+    pane.child(KdlNode::new("start_suspended").arg(suspended))
 }
 
 /// Write layout to a temporary file and return the path
@@ -107,8 +310,12 @@ pub fn write_temp_layout(layout: &str) -> io::Result<std::path::PathBuf> {
     Ok(layout_path)
 }
 
-/// Get the user's preferred editor command
-pub fn get_editor_command() -> String {
+/// Get the user's preferred editor command: the configured one, then
+/// `$VISUAL`, `$EDITOR`, and finally `hx`
+pub fn get_editor_command(configured: Option<&str>) -> String {
+    if let Some(editor) = configured {
+        return editor.to_string();
+    }
     std::env::var("VISUAL")
         .or_else(|_| std::env::var("EDITOR"))
         .unwrap_or_else(|_| "hx".to_string())
@@ -119,25 +326,208 @@ mod tests {
     use super::*;
     use std::path::PathBuf;
 
+    fn claude() -> Vec<String> {
+        vec!["claude".to_string()]
+    }
+
+    fn process(name: &str, command: &str) -> Process {
+        Process {
+            name: name.to_string(),
+            command: command.to_string(),
+            cwd: None,
+            env: Default::default(),
+            suspended: false,
+        }
+    }
+
     #[test]
     fn test_generate_layout() {
         let path = PathBuf::from("/home/user/project.feature");
-        let layout = generate_layout(&path, "hx", Some("bun install"), Some("bun run serve"));
+        let processes = [process("start", "bun run serve")];
+        let layout = generate_layout(PRESETS[0].1, &path, "hx", &claude(), Some("bun install"), &processes, &[]);
 
         assert!(layout.contains("tab name=\"project.feature\""));
         assert!(layout.contains("command \"hx\""));
         assert!(layout.contains("command \"claude\""));
-        assert!(layout.contains("bun install"));
-        assert!(layout.contains("bun run serve"));
+        assert!(layout.contains("pane name=\"install\""));
+        assert!(layout.contains("bun install && touch "));
+        assert!(layout.contains("; do sleep 1; done; bun run serve"));
+    }
+
+    #[test]
+    fn test_generate_layout_exports_env() {
+        let path = PathBuf::from("/home/user/project.feature");
+        let env = vec![
+            ("MAESTRO_PORT".to_string(), "20000".to_string()),
+            ("MAESTRO_PORT_1".to_string(), "20001".to_string()),
+        ];
+        let processes = [process("start", "bun run serve")];
+        let layout = generate_layout(PRESETS[0].1, &path, "hx", &claude(), None, &processes, &env);
+
+        assert!(layout.contains("export MAESTRO_PORT=20000 MAESTRO_PORT_1=20001; bun run serve"));
+    }
+
+    #[test]
+    fn test_generate_layout_processes() {
+        let path = PathBuf::from("/home/user/project.feature");
+        let mut api = process("api", "cargo run");
+        api.cwd = Some("server".to_string());
+        api.env.insert("RUST_LOG".to_string(), "debug".to_string());
+        let mut tests = process("tests", "cargo watch -x test");
+        tests.suspended = true;
+        let layout = generate_layout(PRESETS[0].1, &path, "hx", &claude(), Some("make deps"), &[api, tests], &[]);
+
+        assert!(layout.contains("pane size=\"30%\" stacked=true"));
+        assert!(layout.contains("pane name=\"api\""));
+        assert!(layout.contains("export RUST_LOG=debug; until [ -e "));
+        assert!(layout.contains("cwd \"/home/user/project.feature/server\""));
+        assert!(layout.contains("pane name=\"tests\""));
+        assert!(layout.contains("; do sleep 1; done; cargo watch -x test\""));
+        assert!(layout.contains("start_suspended true"));
+    }
+
+    #[test]
+    fn test_install_gates_every_process() {
+        let path = PathBuf::from("/home/user/project.feature");
+        let mut api = process("api", "cargo run");
+        api.cwd = Some("server".to_string());
+        api.suspended = true;
+        let web = process("web", "bun run dev");
+        let rendered = render_nodes(&process_panes(&path, Some("make deps"), &[api, web], &[]));
+        let panes: Vec<&str> = rendered.split("pane name=").skip(1).collect();
+        let marker = install_done_marker(&path).to_string_lossy().to_string();
+
+        // The install runs in its own pane at the worktree root, even though
+        // the first process is suspended and has its own cwd
+        assert_eq!(panes.len(), 3);
+        assert!(panes[0].starts_with("\"install\""));
+        assert!(panes[0].contains(&format!("make deps && touch {}", marker)));
+        assert!(panes[0].contains("cwd \"/home/user/project.feature\""));
+        assert!(panes[0].contains("start_suspended false"));
+
+        assert!(panes[1].starts_with("\"api\""));
+        assert!(panes[1].contains("cwd \"/home/user/project.feature/server\""));
+        assert!(panes[1].contains("start_suspended true"));
+        for pane in &panes[1..] {
+            assert!(pane.contains(&format!("until [ -e {} ]; do sleep 1; done;", marker)));
+        }
+
+        let rendered = render_nodes(&process_panes(&path, None, &[process("web", "bun run dev")], &[]));
+        assert!(rendered.contains("args \"-c\" \"bun run dev\""));
+        assert!(!rendered.contains("install"));
     }
 
     #[test]
     fn test_generate_layout_no_hooks() {
         let path = PathBuf::from("/home/user/project.feature");
-        let layout = generate_layout(&path, "code", None, None);
+        let layout = generate_layout(PRESETS[0].1, &path, "code", &claude(), None, &[], &[]);
 
         assert!(layout.contains("tab name=\"project.feature\""));
         assert!(layout.contains("command \"code\""));
         assert!(layout.contains("command \"claude\""));
     }
+
+    #[test]
+    fn test_presets_render_valid_kdl() {
+        let path = PathBuf::from("/home/user/project.feature");
+        let processes = [process("start", "echo \"{ready}\"")];
+        for (name, template) in PRESETS {
+            let layout = generate_layout(template, &path, "hx", &claude(), Some("bun install"), &processes, &[]);
+            assert!(parse_layout(&layout).is_ok(), "preset {} is invalid", name);
+            assert!(!layout.contains("{agent}"), "preset {} left a placeholder", name);
+            assert!(layout.contains("echo \\\"{ready}\\\""), "preset {}", name);
+        }
+
+        let layout = generate_layout(
+            &load_template(Some("two-agents"), &path).unwrap(),
+            &path,
+            "hx",
+            &["claude".to_string(), "--model".to_string(), "opus".to_string()],
+            None,
+            &[],
+            &[],
+        );
+        assert_eq!(layout.matches("command \"claude\"").count(), 2);
+        assert_eq!(layout.matches("args \"--model\" \"opus\"").count(), 2);
+        assert!(load_template(Some("no-such-preset"), &path).is_err());
+    }
+
+    #[test]
+    fn test_combine_layouts() {
+        let layouts: Vec<String> = ["/tmp/project.fix-1", "/tmp/project.fix-2"]
+            .iter()
+            .map(|path| generate_layout(PRESETS[0].1, Path::new(path), "hx", &claude(), None, &[], &[]))
+            .collect();
+        let combined = combine_layouts(&layouts).unwrap();
+
+        let doc = parse_layout(&combined).unwrap();
+        let children = doc.nodes()[0].children().unwrap().nodes();
+        let named = |name: &'static str| children.iter().filter(move |n| n.name().value() == name);
+        assert_eq!(named("default_tab_template").count(), 1);
+        let tabs: Vec<&str> = named("tab")
+            .filter_map(|n| n.get("name")?.value().as_string())
+            .collect();
+        assert_eq!(tabs, ["project.fix-1", "project.fix-2"]);
+    }
+
+    #[test]
+    fn test_render_template_indents_values() {
+        let rendered = render_template(
+            "pane {\n    {body}\n}\n",
+            &[("body", "command \"hx\"\nargs \"/tmp/{body}\"".to_string())],
+        );
+        assert_eq!(rendered, "pane {\n    command \"hx\"\n    args \"/tmp/{body}\"\n}\n");
+    }
+
+    #[test]
+    fn test_generate_layout_escapes_paths_and_splits_editor() {
+        let path = PathBuf::from("/tmp/we\"ird\nproject\\");
+        let processes = [process("a \"b\"", "echo $HOME")];
+        let layout = generate_layout(PRESETS[0].1, &path, "code --wait", &claude(), None, &processes, &[]);
+
+        let doc = parse_layout(&layout).unwrap();
+        let tab = doc.nodes()[0].children().unwrap().get("tab").unwrap();
+        let path_str = path.to_string_lossy().to_string();
+        assert_eq!(tab.get("cwd").unwrap().value().as_string(), Some(path_str.as_str()));
+        assert!(layout.contains(&format!(
+            "command \"code\"\n                    args \"--wait\" \"{}\"",
+            escape_string(&path_str)
+        )));
+        assert!(layout.contains("pane name=\"a \\\"b\\\"\""));
+    }
+
+    #[test]
+    fn test_parse_layout() {
+        // Values Zellij accepts that aren't plain strings
+        let layout = "layout {\n    pane size=0.5 split=0x1 (u8)1 1e3 {\n        args r#\"raw\"#\n    }\n}\n";
+        assert!(parse_layout(layout).is_ok());
+
+        let err = |input: &str| parse_layout(input).unwrap_err().to_string();
+        assert!(err("layout {\n    pane {\n}\n").starts_with("Invalid KDL (line "));
+        assert!(err("layout {\n}\n}\n").starts_with("Invalid KDL (line 3: "));
+        assert!(err("pane size=big").starts_with("Invalid KDL (line 1: "));
+    }
+
+    #[test]
+    fn test_render_nodes() {
+        let pane = KdlNode::new("pane")
+            .prop("size", 1)
+            .prop("borderless", true)
+            .child(KdlNode::new("command").arg("bash"))
+            .child(KdlNode::new("args").arg("-c").arg("echo \"hi\"\nexit"));
+        assert_eq!(
+            render_nodes(&[pane]),
+            "pane size=1 borderless=true {\n    command \"bash\"\n    args \"-c\" \"echo \\\"hi\\\"\\nexit\"\n}"
+        );
+    }
+
+    #[test]
+    fn test_env_values_are_shell_quoted() {
+        let path = PathBuf::from("/home/user/project.feature");
+        let mut api = process("api", "cargo run");
+        api.env.insert("GREETING".to_string(), "hello; rm -rf ~".to_string());
+        let layout = generate_layout(PRESETS[0].1, &path, "hx", &claude(), None, &[api], &[]);
+
+        assert!(layout.contains("export GREETING='hello; rm -rf ~'; cargo run"));
+    }
 }
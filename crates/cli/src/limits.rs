@@ -0,0 +1,340 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::config::{Config, Limits};
+use crate::instance::{unix_now, Instance, InstanceStore};
+use crate::paths;
+
+/// How often `--wait` checks for a free slot
+const WAIT_INTERVAL: Duration = Duration::from_secs(2);
+const RESERVATIONS_FILE: &str = "reservations.json";
+/// Held while the limits are checked and a reservation recorded
+const LOCK_FILE: &str = "limits.lock";
+/// Seconds a reservation holds a slot for an agent that hasn't registered yet
+const RESERVATION_GRACE: u64 = 120;
+
+/// Agents and processes running now
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Usage {
+    pub agents: usize,
+    /// Only counted when `max_processes` is set, as it reads every
+    /// worktree's config
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub processes: Option<usize>,
+}
+
+impl Usage {
+    pub fn current(instances: &[Instance], limits: &Limits) -> Self {
+        let processes = limits.max_processes.map(|_| {
+            let worktrees: BTreeSet<&str> =
+                instances.iter().filter_map(|i| i.cwd.as_deref()).collect();
+            worktrees
+                .into_iter()
+                .filter_map(|cwd| Config::load(Path::new(cwd)).ok())
+                .map(|config| process_count(&config))
+                .sum()
+        });
+        Usage {
+            agents: instances.len(),
+            processes,
+        }
+    }
+}
+
+/// A slot taken for an agent that is being launched but hasn't registered
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Reservation {
+    repo: String,
+    branch: String,
+    processes: usize,
+    reserved_at: u64,
+}
+
+impl Reservation {
+    /// Whether the reservation still holds a slot: its agent hasn't
+    /// registered and the grace period isn't over
+    fn is_pending(&self, instances: &[Instance], now: u64) -> bool {
+        now.saturating_sub(self.reserved_at) < RESERVATION_GRACE
+            && !instances.iter().any(|i| {
+                i.repo.as_deref() == Some(self.repo.as_str())
+                    && i.branch.as_deref() == Some(self.branch.as_str())
+            })
+    }
+}
+
+/// Slots taken by agents being launched, so launches running at the same
+/// time don't all fit in the same free slot
+struct Reservations {
+    path: PathBuf,
+}
+
+impl Reservations {
+    fn new() -> Self {
+        Self {
+            path: paths::state_dir().join(RESERVATIONS_FILE),
+        }
+    }
+
+    fn load(&self) -> Vec<Reservation> {
+        fs::read_to_string(&self.path)
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    /// Exclusive lock on the reservations, released when the file is dropped
+    fn lock(&self) -> io::Result<File> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.path.with_file_name(LOCK_FILE))?;
+        file.lock()?;
+        Ok(file)
+    }
+
+    /// Reserve a slot for an agent on each of `branches`, each starting
+    /// `processes` processes. Returns why they don't fit next to `instances`
+    /// and the pending reservations, if they don't.
+    fn reserve(
+        &self,
+        limits: &Limits,
+        instances: &[Instance],
+        repo: &str,
+        branches: &[String],
+        processes: usize,
+        now: u64,
+    ) -> io::Result<Option<String>> {
+        let _lock = self.lock()?;
+        let mut pending = self.load();
+        // A branch launched again replaces its earlier reservation
+        pending.retain(|r| {
+            r.is_pending(instances, now) && !(r.repo == repo && branches.contains(&r.branch))
+        });
+
+        let mut usage = Usage::current(instances, limits);
+        usage.agents += pending.len();
+        if let Some(running) = &mut usage.processes {
+            *running += pending.iter().map(|r| r.processes).sum::<usize>();
+        }
+        if let Some(reason) = exceeded(limits, usage, branches.len(), branches.len() * processes) {
+            return Ok(Some(reason));
+        }
+
+        pending.extend(branches.iter().map(|branch| Reservation {
+            repo: repo.to_string(),
+            branch: branch.clone(),
+            processes,
+            reserved_at: now,
+        }));
+        fs::write(&self.path, serde_json::to_string_pretty(&pending)?)?;
+        Ok(None)
+    }
+}
+
+/// Processes a worktree starts with its layout, not counting suspended ones
+pub fn process_count(config: &Config) -> usize {
+    config.processes().iter().filter(|p| !p.suspended).count()
+}
+
+/// Why `agents` more agents with `processes` more processes don't fit next to
+/// `usage`, if they don't
+fn exceeded(limits: &Limits, usage: Usage, agents: usize, processes: usize) -> Option<String> {
+    if let Some(max) = limits.max_agents
+        && usage.agents + agents > max
+    {
+        return Some(format!("{} of {} agents running", usage.agents, max));
+    }
+    if let Some(max) = limits.max_processes {
+        let running = usage.processes.unwrap_or(0);
+        if running + processes > max {
+            return Some(format!("{} of {} processes running", running, max));
+        }
+    }
+    None
+}
+
+/// Reserve slots for agents about to be launched on `branches` of `repo`,
+/// each starting `processes` processes. A slot stays taken until the agent
+/// registers or `RESERVATION_GRACE` runs out. Fails when the limits are
+/// reached, unless `wait`ing for other agents to exit.
+pub fn acquire(
+    limits: &Limits,
+    repo: &str,
+    branches: &[String],
+    processes: usize,
+    wait: bool,
+) -> io::Result<()> {
+    if limits.max_agents.is_none() && limits.max_processes.is_none() {
+        return Ok(());
+    }
+    let idle = Usage {
+        agents: 0,
+        processes: Some(0),
+    };
+    let agents = branches.len();
+    if exceeded(limits, idle, agents, agents * processes).is_some() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "{} agent(s) with {} process(es) don't fit in [limits] even with nothing else running",
+                agents,
+                agents * processes
+            ),
+        ));
+    }
+
+    let reservations = Reservations::new();
+    let store = InstanceStore::new();
+    let mut waiting = false;
+    loop {
+        let refused =
+            reservations.reserve(limits, &store.load(), repo, branches, processes, unix_now())?;
+        let Some(reason) = refused else {
+            return Ok(());
+        };
+        if !wait {
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!("Limit reached: {}. Close an agent or pass --wait", reason),
+            ));
+        }
+        if !waiting {
+            eprintln!("Limit reached: {}. Waiting for an agent to exit...", reason);
+            waiting = true;
+        }
+        std::thread::sleep(WAIT_INTERVAL);
+    }
+}
+
+/// Load of the machine, from `/proc`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Host {
+    /// Load average over the last minute
+    pub load: f64,
+    pub cpus: usize,
+    /// Share of memory in use, in percent
+    pub memory_percent: f64,
+}
+
+/// Current host load; `None` where there is no `/proc`
+pub fn host() -> Option<Host> {
+    let load = parse_loadavg(&fs::read_to_string("/proc/loadavg").ok()?)?;
+    let memory_percent = parse_meminfo(&fs::read_to_string("/proc/meminfo").ok()?)?;
+    let cpus = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1);
+    Some(Host {
+        load,
+        cpus,
+        memory_percent,
+    })
+}
+
+fn parse_loadavg(content: &str) -> Option<f64> {
+    content.split_whitespace().next()?.parse().ok()
+}
+
+/// Memory in use, in percent, from `MemTotal` and `MemAvailable`
+fn parse_meminfo(content: &str) -> Option<f64> {
+    let field = |name: &str| {
+        content
+            .lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+            .and_then(|rest| rest.split_whitespace().next())
+            .and_then(|kb| kb.parse::<f64>().ok())
+    };
+    let total = field("MemTotal")?;
+    let available = field("MemAvailable")?;
+    if total <= 0.0 {
+        return None;
+    }
+    Some((total - available) / total * 100.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instance::Status;
+
+    fn limits(max_agents: Option<usize>, max_processes: Option<usize>) -> Limits {
+        Limits {
+            max_agents,
+            max_processes,
+        }
+    }
+
+    #[test]
+    fn test_exceeded() {
+        let usage = Usage {
+            agents: 3,
+            processes: Some(5),
+        };
+        assert_eq!(exceeded(&limits(None, None), usage, 10, 10), None);
+        assert_eq!(exceeded(&limits(Some(4), None), usage, 1, 2), None);
+        assert_eq!(
+            exceeded(&limits(Some(4), None), usage, 2, 0).as_deref(),
+            Some("3 of 4 agents running")
+        );
+        assert_eq!(exceeded(&limits(None, Some(7)), usage, 1, 2), None);
+        assert_eq!(
+            exceeded(&limits(Some(4), Some(7)), usage, 1, 3).as_deref(),
+            Some("5 of 7 processes running")
+        );
+    }
+
+    #[test]
+    fn test_acquire_refuses_what_never_fits() {
+        let branches = ["a", "b", "c"].map(String::from);
+        let err = acquire(&limits(Some(2), None), "app", &branches, 0, true).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_reservations_hold_slots_until_registered() {
+        let dir = std::env::temp_dir().join(format!("maestro-limits-{}", std::process::id()));
+        let reservations = Reservations {
+            path: dir.join(RESERVATIONS_FILE),
+        };
+        let limits = limits(Some(2), None);
+        let launch = |instances: &[Instance], branch: &str, now| {
+            reservations
+                .reserve(&limits, instances, "app", &[branch.to_string()], 1, now)
+                .unwrap()
+        };
+
+        // Launches in a row count each other before any agent registers
+        assert_eq!(launch(&[], "a", 100), None);
+        assert_eq!(launch(&[], "b", 100), None);
+        assert_eq!(launch(&[], "c", 100).as_deref(), Some("2 of 2 agents running"));
+        // Launching the same branch again takes its earlier slot
+        assert_eq!(launch(&[], "b", 101), None);
+
+        // A registered agent counts once, not as an instance and a reservation
+        let a = Instance::for_test("app.a", Some("app"), Some("a"), Status::Running);
+        assert_eq!(
+            launch(std::slice::from_ref(&a), "c", 102).as_deref(),
+            Some("2 of 2 agents running")
+        );
+
+        // Reservations of agents that never registered run out
+        assert_eq!(launch(&[a], "c", 101 + RESERVATION_GRACE), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_proc() {
+        assert_eq!(parse_loadavg("1.25 0.80 0.50 2/345 6789\n"), Some(1.25));
+        let meminfo = "MemTotal:       16000000 kB\nMemFree:         2000000 kB\nMemAvailable:    4000000 kB\n";
+        assert_eq!(parse_meminfo(meminfo), Some(75.0));
+        assert_eq!(parse_meminfo("MemFree: 1 kB\n"), None);
+    }
+}
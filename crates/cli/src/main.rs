@@ -1,13 +1,32 @@
+mod agent;
+mod beads;
 mod config;
+mod eventlog;
+mod hooks;
 mod instance;
 mod layout;
+mod limits;
+mod notify;
+mod paths;
+mod ports;
+mod queue;
+mod report;
+mod seed;
+mod tool;
+mod transcript;
+mod webhook;
 mod worktree;
+mod zellij;
 
+use agent::AgentKind;
 use clap::{Parser, Subcommand};
-use instance::{InstanceStore, Status};
+use eventlog::LogEntry;
+use instance::{unix_now, InstanceStore, InstanceUpdate, Status};
 use serde::Deserialize;
 use std::io::{self, Read};
 use std::path::Path;
+use tool::ToolCall;
+use webhook::Change;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
@@ -45,7 +64,7 @@ enum Commands {
     /// Update status of current instance
     Update {
         /// Status to set
-        #[arg(value_parser = ["running", "waiting"])]
+        #[arg(value_parser = ["running", "waiting", "permission"])]
         status: String,
     },
 
@@ -64,32 +83,284 @@ enum Commands {
         #[command(subcommand)]
         command: WtCommands,
     },
+
+    /// Show the event log of agent activity
+    Log {
+        /// Only events of this instance (folder name or pane id)
+        #[arg(long)]
+        instance: Option<String>,
+
+        /// Only events in this repository
+        #[arg(long)]
+        repo: Option<String>,
+
+        /// Only events newer than this, e.g. 30m, 2h or 1d
+        #[arg(long, value_parser = eventlog::parse_duration)]
+        since: Option<u64>,
+
+        /// Only events older than this, e.g. 30m, 2h or 1d
+        #[arg(long, value_parser = eventlog::parse_duration)]
+        until: Option<u64>,
+
+        /// Show only the last N events
+        #[arg(short = 'n', long)]
+        limit: Option<usize>,
+
+        /// Keep printing new events as they happen
+        #[arg(short, long)]
+        follow: bool,
+
+        /// Output as JSON lines
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Summarise agent activity per repo and worktree
+    Report {
+        /// Period to report on, e.g. 8h, 1d or 1w
+        #[arg(long, default_value = "1d", value_parser = eventlog::parse_duration)]
+        since: u64,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = report::ReportFormat::Text)]
+        format: report::ReportFormat,
+    },
+
+    /// Start N agents on the same task, each in its own worktree
+    Fanout {
+        /// Base name; worktrees are created for branches <name>-1..<name>-N
+        name: String,
+
+        /// Number of worktrees and agents
+        #[arg(short = 'n', long, default_value_t = 2)]
+        count: usize,
+
+        /// Initial prompt for every agent
+        #[arg(long)]
+        prompt: Option<String>,
+
+        /// Agent to run instead of the configured one
+        #[arg(long, value_enum)]
+        agent: Option<AgentKind>,
+
+        /// Extra arguments for the agent
+        #[arg(long, allow_hyphen_values = true)]
+        agent_args: Option<String>,
+
+        /// Print the generated Zellij layout instead of launching it
+        #[arg(long)]
+        print_layout: bool,
+
+        /// Wait for a free slot when [limits] are reached instead of failing
+        #[arg(long)]
+        wait: bool,
+    },
+
+    /// Configuration commands
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+
+    /// Queue prompts for idle agents
+    Queue {
+        #[command(subcommand)]
+        command: QueueCommands,
+    },
+
+    /// Webhook commands
+    Webhook {
+        #[command(subcommand)]
+        command: WebhookCommands,
+    },
+
+    /// Notify once an instance has been waiting through the quiet period
+    #[command(hide = true)]
+    Notify {
+        #[arg(long)]
+        pane_id: u32,
+    },
+
+    /// Run agents that have no hooks of their own
+    #[command(hide = true)]
+    Agent {
+        #[command(subcommand)]
+        command: AgentCommands,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum AgentCommands {
+    /// Register the pane and run the agent, tracking its status
+    Run {
+        /// Agent kind recorded for the instance
+        #[arg(long, value_enum)]
+        kind: AgentKind,
+
+        /// Agent command and arguments
+        #[arg(last = true, required = true)]
+        command: Vec<String>,
+    },
+
+    /// Follow an agent process and update its status until it exits
+    Watch {
+        #[arg(long)]
+        pane_id: u32,
+
+        #[arg(long)]
+        pid: u32,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum QueueCommands {
+    /// Queue a prompt for the next agent of a repository to go idle
+    Add {
+        /// The prompt
+        prompt: String,
+
+        /// Repository name (default: the current one)
+        #[arg(long)]
+        repo: Option<String>,
+    },
+
+    /// List pending prompts
+    List {
+        /// Include prompts that were already handed out
+        #[arg(long)]
+        all: bool,
+
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Remove pending prompts
+    Cancel {
+        /// Ids shown by `queue list`
+        #[arg(required = true)]
+        ids: Vec<u32>,
+    },
+
+    /// Start pending prompts in new worktrees, up to the concurrency limit
+    #[command(hide = true)]
+    Dispatch {
+        #[arg(long)]
+        repo: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum WebhookCommands {
+    /// Send a sample event to the configured webhook
+    Test,
+
+    /// Send events read from stdin, retrying failures
+    #[command(hide = true)]
+    Deliver,
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommands {
+    /// Print the effective config and where each value comes from
+    Show,
 }
 
 #[derive(Subcommand)]
 pub enum WtCommands {
     /// List all worktrees
-    List,
+    List {
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
 
     /// Switch to a worktree (creates if it doesn't exist)
     Switch {
-        /// Branch name
-        branch: String,
+        /// Branch name (derived from the issue title with --issue)
+        #[arg(required_unless_present = "issue")]
+        branch: Option<String>,
+
+        /// Start from a beads issue: the agent gets its title and description
+        /// as the initial prompt
+        #[arg(long)]
+        issue: Option<String>,
+
+        /// Print the generated Zellij layout instead of launching it
+        #[arg(long)]
+        print_layout: bool,
+
+        /// Wait for a free slot when [limits] are reached instead of failing
+        #[arg(long)]
+        wait: bool,
+
+        /// Agent to run instead of the configured one
+        #[arg(long, value_enum)]
+        agent: Option<AgentKind>,
+
+        /// Extra arguments for the agent
+        #[arg(long, allow_hyphen_values = true)]
+        agent_args: Option<String>,
     },
 
-    /// Remove current worktree and switch back to main
-    Remove,
+    /// Remove worktrees by branch (the current worktree if none given)
+    Remove {
+        /// Branches whose worktrees to remove
+        branches: Vec<String>,
+
+        /// Discard uncommitted changes and unmerged commits (asks for confirmation)
+        #[arg(long)]
+        force: bool,
+
+        /// Keep the branch after removing its worktree
+        #[arg(long)]
+        keep_branch: bool,
+
+        /// Don't ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
 
     /// Squash-merge current worktree to main and cleanup
     Merge,
+
+    /// Compare attempts at the same task, then merge one and remove the rest
+    Compare {
+        /// Branches of the worktrees to compare
+        #[arg(required = true, num_args = 2..)]
+        branches: Vec<String>,
+
+        /// Skip the configured check command
+        #[arg(long)]
+        no_check: bool,
+    },
+
+    /// Remove worktrees that are merged, missing or stale
+    Prune {
+        /// Also prune worktrees untouched for this many days
+        #[arg(long)]
+        days: Option<u64>,
+
+        /// Don't ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
 }
 
+/// Payload Claude Code passes to hooks on stdin
 #[derive(Deserialize)]
 struct HookInput {
     cwd: Option<String>,
+    session_id: Option<String>,
+    /// JSONL transcript of the session so far
+    transcript_path: Option<String>,
+    /// e.g. `PreToolUse`, `Stop`
+    hook_event_name: Option<String>,
+    tool_name: Option<String>,
+    tool_input: Option<serde_json::Value>,
 }
 
-fn get_pane_id() -> Option<u32> {
+pub(crate) fn get_pane_id() -> Option<u32> {
     std::env::var("ZELLIJ_PANE_ID")
         .ok()
         .and_then(|s| s.parse().ok())
@@ -101,6 +372,51 @@ fn read_stdin_json() -> Option<HookInput> {
     serde_json::from_str(&buffer).ok()
 }
 
+/// The session id and transcript summary from a hook payload. A transcript
+/// that can't be read is skipped so the hook never fails on it.
+fn session_update(input: Option<&HookInput>) -> InstanceUpdate {
+    let Some(input) = input else {
+        return InstanceUpdate::default();
+    };
+    InstanceUpdate {
+        session_id: input.session_id.clone(),
+        transcript: input
+            .transcript_path
+            .as_ref()
+            .and_then(|path| transcript::summarize(Path::new(path)).ok()),
+        ..InstanceUpdate::default()
+    }
+}
+
+/// The tool a `PreToolUse` payload is about to run; other events end the
+/// current tool
+fn started_tool(input: Option<&HookInput>) -> Option<ToolCall> {
+    let input = input?;
+    if input.hook_event_name.as_deref() != Some("PreToolUse") {
+        return None;
+    }
+    let name = input.tool_name.as_deref()?;
+    Some(ToolCall::new(
+        name,
+        input.tool_input.as_ref(),
+        input.cwd.as_deref(),
+        unix_now(),
+    ))
+}
+
+/// Append a hook call to the event log, with what the payload adds
+fn log_hook(mut entry: LogEntry, input: Option<&HookInput>) {
+    if let Some(input) = input {
+        if let Some(event) = &input.hook_event_name {
+            entry.event = event.clone();
+        }
+        entry.tool = input.tool_name.clone();
+        entry.session_id = input.session_id.clone().or(entry.session_id);
+        entry.cwd = entry.cwd.or_else(|| input.cwd.clone());
+    }
+    eventlog::record(&entry);
+}
+
 fn main() {
     let cli = Cli::parse();
 
@@ -130,8 +446,8 @@ fn main() {
                 }
             };
 
-            let input = read_stdin_json();
-            let cwd = input.and_then(|i| i.cwd).unwrap_or_else(|| {
+            let mut input = read_stdin_json();
+            let cwd = input.as_mut().and_then(|i| i.cwd.take()).unwrap_or_else(|| {
                 std::env::current_dir()
                     .map(|p| p.to_string_lossy().to_string())
                     .unwrap_or_default()
@@ -142,7 +458,15 @@ fn main() {
                 .map(|s| s.to_string_lossy().to_string())
                 .unwrap_or_else(|| cwd.clone());
 
-            store.register(pane_id, folder)
+            let result = store
+                .register(pane_id, folder, cwd, AgentKind::Claude, instance::batch_from_env())
+                .and_then(|instance| {
+                    webhook::emit(vec![Change::Registered(instance)]);
+                    store.update_instance(pane_id, session_update(input.as_ref()))
+                })
+                .map(|_| ());
+            log_hook(LogEntry::new("register", pane_id, store.get(pane_id).as_ref()), input.as_ref());
+            result
         }
 
         Commands::Update { status } => {
@@ -154,11 +478,51 @@ fn main() {
                 }
             };
 
-            // Consume stdin (required by hooks)
-            let _ = read_stdin_json();
+            let input = read_stdin_json();
 
-            let status: Status = status.parse().unwrap();
-            store.update_status(pane_id, status)
+            let mut status: Status = status.parse().unwrap();
+            // An agent that finished its turn carries on with the next queued
+            // prompt instead of waiting
+            let queued = match (&input, store.get(pane_id)) {
+                (Some(input), Some(instance))
+                    if status == Status::Waiting
+                        && input.hook_event_name.as_deref() == Some("Stop") =>
+                {
+                    queue::on_idle(&instance)
+                }
+                _ => None,
+            };
+            if queued.is_some() {
+                status = Status::Running;
+            }
+            // The transcript goes in the same write so events about the
+            // change carry the agent's latest message
+            let update = InstanceUpdate {
+                // A permission prompt is about the current tool; keep it
+                tool: (status != Status::Permission).then(|| started_tool(input.as_ref())),
+                status: Some(status),
+                ..session_update(input.as_ref())
+            };
+            let result = store
+                .update_instance(pane_id, update)
+                .map(|change| {
+                    if change.is_some() && status != Status::Running {
+                        notify::schedule(pane_id);
+                    }
+                    webhook::emit(change.into_iter().collect());
+                });
+            log_hook(LogEntry::new("update", pane_id, store.get(pane_id).as_ref()), input.as_ref());
+            if let Some(item) = &queued {
+                // The agent won't be told about a prompt the failed hook took
+                if result.is_err() {
+                    if let Err(e) = queue::Queue::new().requeue(item.id) {
+                        eprintln!("Warning: Could not requeue prompt #{}: {}", item.id, e);
+                    }
+                } else {
+                    println!("{}", queue::hook_response(item));
+                }
+            }
+            result
         }
 
         Commands::Unregister => {
@@ -170,30 +534,68 @@ fn main() {
                 }
             };
 
-            // Consume stdin (required by hooks)
-            let _ = read_stdin_json();
+            let input = read_stdin_json();
 
-            store.unregister(pane_id)
+            // Keep the instance's details for the log, minus its status
+            let mut entry = LogEntry::new("unregister", pane_id, store.get(pane_id).as_ref());
+            entry.status = None;
+            let result = store.unregister(pane_id).map(|removed| {
+                webhook::emit(removed.into_iter().map(Change::Unregistered).collect());
+            });
+            log_hook(entry, input.as_ref());
+            result
         }
 
         Commands::List { json } => {
             let instances = store.load();
             if json {
+                let limits = std::env::current_dir()
+                    .ok()
+                    .and_then(|dir| config::Config::load(&dir).ok())
+                    .map(|c| c.limits)
+                    .unwrap_or_default();
+                let usage = limits::Usage::current(&instances, &limits);
                 let output = serde_json::json!({
                     "version": VERSION,
                     "build": BUILD_PROFILE,
-                    "instances": instances
+                    "instances": instances,
+                    "limits": {
+                        "max_agents": limits.max_agents,
+                        "max_processes": limits.max_processes,
+                        "agents": usage.agents,
+                        "processes": usage.processes,
+                    },
+                    "host": limits::host(),
                 });
                 println!("{}", output);
             } else if instances.is_empty() {
                 println!("No Claude Code instances registered");
             } else {
                 for inst in instances {
-                    let icon = match inst.status {
-                        Status::Running => "⚡",
-                        Status::Waiting => "⏳",
+                    let agent = if inst.agent.has_hooks() {
+                        String::new()
+                    } else {
+                        format!(" [{}]", inst.agent)
                     };
-                    println!("{} {} (pane {})", icon, inst.folder, inst.pane_id);
+                    let cost = inst
+                        .transcript
+                        .as_ref()
+                        .map(|t| format!(" ${:.2}", t.cost_usd))
+                        .unwrap_or_default();
+                    let tool = inst
+                        .current_tool
+                        .as_ref()
+                        .map(|t| format!(" · {}", t.description))
+                        .unwrap_or_default();
+                    println!(
+                        "{} {}{} (pane {}){}{}",
+                        inst.status.icon(),
+                        inst.folder,
+                        agent,
+                        inst.pane_id,
+                        cost,
+                        tool
+                    );
                 }
             }
             Ok(())
@@ -202,6 +604,69 @@ fn main() {
         Commands::Wt { command } => {
             worktree::run(command)
         }
+
+        Commands::Log {
+            instance,
+            repo,
+            since,
+            until,
+            limit,
+            follow,
+            json,
+        } => {
+            let opts = eventlog::LogOptions {
+                filter: eventlog::Filter {
+                    instance,
+                    repo,
+                    since: since.map(eventlog::time_ago),
+                    until: until.map(eventlog::time_ago),
+                },
+                limit,
+                follow,
+                json,
+            };
+            eventlog::cmd_log(&opts)
+        }
+
+        Commands::Report { since, format } => report::cmd_report(since, format),
+
+        Commands::Fanout {
+            name,
+            count,
+            prompt,
+            agent,
+            agent_args,
+            print_layout,
+            wait,
+        } => {
+            let agent = agent::AgentOptions {
+                kind: agent,
+                args: agent_args,
+                prompt,
+                batch: Some(name.clone()),
+            };
+            worktree::cmd_fanout(&name, count, &agent, print_layout, wait)
+        }
+
+        Commands::Config { command } => {
+            config::run(command)
+        }
+
+        Commands::Webhook { command } => {
+            webhook::run(command)
+        }
+
+        Commands::Queue { command } => {
+            queue::run(command)
+        }
+
+        Commands::Notify { pane_id } => {
+            notify::cmd_notify(pane_id)
+        }
+
+        Commands::Agent { command } => {
+            agent::run(command)
+        }
     };
 
     if let Err(e) = result {
@@ -0,0 +1,228 @@
+use std::io::{self, Error, ErrorKind};
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use crate::config::{Config, Notify, NotifyBackend};
+use crate::instance::{unix_now, Instance, InstanceStore, Status};
+
+/// Start a detached `maestro notify` for an instance that just started
+/// waiting. It only notifies if the instance is still waiting once the quiet
+/// period is over.
+pub fn schedule(pane_id: u32) {
+    let Ok(exe) = std::env::current_exe() else {
+        return;
+    };
+    // Own process group, so the agent's hooks don't wait on (or kill) it
+    let _ = Command::new(exe)
+        .args(["notify", "--pane-id"])
+        .arg(pane_id.to_string())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn();
+}
+
+/// Wait out the quiet period, then notify about every instance of the same
+/// repository that has been waiting since, including ones that started
+/// waiting around the same time. Other repositories are notified by their own
+/// instances' processes, with their own config.
+pub fn cmd_notify(pane_id: u32) -> io::Result<()> {
+    let store = InstanceStore::new();
+    let Some(instance) = store.get(pane_id) else {
+        return Ok(());
+    };
+    let cwd = match &instance.cwd {
+        Some(cwd) => PathBuf::from(cwd),
+        None => std::env::current_dir()?,
+    };
+    // Nobody sees errors from this detached process; fall back to defaults
+    let config = Config::load(&cwd).map(|c| c.notify).unwrap_or_default();
+    if !config.enabled || config.backends.is_empty() {
+        return Ok(());
+    }
+
+    std::thread::sleep(Duration::from_secs(config.quiet_period + config.batch_window));
+
+    let now = unix_now();
+    let due = store.update(|instances| {
+        let mut due = Vec::new();
+        for other in instances {
+            if same_repo(other, &instance) && is_due(other, now, config.quiet_period) {
+                other.notified = true;
+                due.push(other.clone());
+            }
+        }
+        due
+    })?;
+    if due.is_empty() {
+        return Ok(());
+    }
+
+    let (title, body) = message(&due);
+    for backend in &config.backends {
+        if let Err(e) = send(*backend, &config, &title, &body) {
+            eprintln!("Warning: Could not notify: {}", e);
+        }
+    }
+    Ok(())
+}
+
+/// Whether an instance has been waiting for at least `quiet_period` seconds
+/// without a notification
+fn is_due(instance: &Instance, now: u64, quiet_period: u64) -> bool {
+    instance.status != Status::Running
+        && !instance.notified
+        && instance
+            .status_since
+            .is_some_and(|since| now.saturating_sub(since) >= quiet_period)
+}
+
+/// Whether two instances share a repository, and so a notify config. An
+/// instance outside a repository only matches itself.
+fn same_repo(a: &Instance, b: &Instance) -> bool {
+    match (&a.repo, &b.repo) {
+        (Some(a), Some(b)) => a == b,
+        _ => a.pane_id == b.pane_id,
+    }
+}
+
+fn what(status: Status) -> &'static str {
+    match status {
+        Status::Permission => "needs permission",
+        Status::Running | Status::Waiting => "is waiting",
+    }
+}
+
+/// Title and body of the notification for the instances in `due`
+fn message(due: &[Instance]) -> (String, String) {
+    if let [instance] = due {
+        let title = format!("{} {}", instance.folder, what(instance.status));
+        let body = instance
+            .transcript
+            .as_ref()
+            .and_then(|t| t.last_message.as_deref())
+            .and_then(|m| m.lines().next())
+            .unwrap_or(match instance.status {
+                Status::Permission => "Waiting for permission",
+                Status::Running | Status::Waiting => "Waiting for input",
+            })
+            .to_string();
+        return (title, body);
+    }
+
+    let title = format!("{} agents are waiting", due.len());
+    let body = due
+        .iter()
+        .map(|i| format!("{} {}", i.folder, what(i.status)))
+        .collect::<Vec<_>>()
+        .join(", ");
+    (title, body)
+}
+
+fn send(backend: NotifyBackend, config: &Notify, title: &str, body: &str) -> io::Result<()> {
+    match backend {
+        NotifyBackend::Desktop => desktop(title, body),
+        NotifyBackend::Command => {
+            let cmd = config.command.as_deref().ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    "The command backend needs [notify] command",
+                )
+            })?;
+            run(Command::new("sh")
+                .args(["-c", cmd])
+                .env("MAESTRO_NOTIFY_TITLE", title)
+                .env("MAESTRO_NOTIFY_BODY", body))
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn desktop(title: &str, body: &str) -> io::Result<()> {
+    let quote = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"");
+    let script = format!(
+        "display notification \"{}\" with title \"{}\"",
+        quote(body),
+        quote(title)
+    );
+    run(Command::new("osascript").args(["-e", &script]))
+}
+
+#[cfg(not(target_os = "macos"))]
+fn desktop(title: &str, body: &str) -> io::Result<()> {
+    run(Command::new("notify-send").args(["--app-name=maestro", title, body]))
+}
+
+fn run(command: &mut Command) -> io::Result<()> {
+    let status = command.stdin(Stdio::null()).status()?;
+    if !status.success() {
+        return Err(Error::new(
+            ErrorKind::Other,
+            format!("{:?} failed ({})", command.get_program(), status),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instance(folder: &str, status: Status, since: u64) -> Instance {
+        Instance {
+            status_since: Some(since),
+            ..Instance::for_test(folder, None, None, status)
+        }
+    }
+
+    #[test]
+    fn test_is_due() {
+        assert!(is_due(&instance("a", Status::Waiting, 100), 110, 10));
+        assert!(is_due(&instance("a", Status::Permission, 100), 110, 10));
+        assert!(!is_due(&instance("a", Status::Waiting, 100), 109, 10));
+        assert!(!is_due(&instance("a", Status::Running, 100), 110, 10));
+
+        let mut notified = instance("a", Status::Waiting, 100);
+        notified.notified = true;
+        assert!(!is_due(&notified, 110, 10));
+    }
+
+    #[test]
+    fn test_same_repo() {
+        let app = |pane_id| Instance {
+            pane_id,
+            repo: Some("app".to_string()),
+            ..instance("app", Status::Waiting, 0)
+        };
+        let lib = Instance {
+            pane_id: 3,
+            repo: Some("lib".to_string()),
+            ..instance("lib", Status::Waiting, 0)
+        };
+        let loose = |pane_id| Instance {
+            pane_id,
+            ..instance("tmp", Status::Waiting, 0)
+        };
+        assert!(same_repo(&app(1), &app(2)));
+        assert!(!same_repo(&app(1), &lib));
+        assert!(!same_repo(&loose(4), &loose(5)));
+        assert!(same_repo(&loose(4), &loose(4)));
+    }
+
+    #[test]
+    fn test_message() {
+        let (title, body) = message(&[instance("app.fix-1", Status::Permission, 0)]);
+        assert_eq!(title, "app.fix-1 needs permission");
+        assert_eq!(body, "Waiting for permission");
+
+        let (title, body) = message(&[
+            instance("app.fix-1", Status::Waiting, 0),
+            instance("app.fix-2", Status::Permission, 0),
+        ]);
+        assert_eq!(title, "2 agents are waiting");
+        assert_eq!(body, "app.fix-1 is waiting, app.fix-2 needs permission");
+    }
+}
@@ -0,0 +1,23 @@
+use std::path::PathBuf;
+
+/// Directory for state that should survive reboots (`$XDG_STATE_HOME/maestro`)
+pub fn state_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("XDG_STATE_HOME").filter(|d| !d.is_empty()) {
+        return PathBuf::from(dir).join("maestro");
+    }
+    match std::env::var_os("HOME") {
+        Some(home) => PathBuf::from(home).join(".local/state/maestro"),
+        None => std::env::temp_dir().join("maestro-ai"),
+    }
+}
+
+/// Directory for user configuration (`$XDG_CONFIG_HOME/maestro`)
+pub fn config_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("XDG_CONFIG_HOME").filter(|d| !d.is_empty()) {
+        return PathBuf::from(dir).join("maestro");
+    }
+    match std::env::var_os("HOME") {
+        Some(home) => PathBuf::from(home).join(".config/maestro"),
+        None => std::env::temp_dir().join("maestro-ai"),
+    }
+}
@@ -0,0 +1,181 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+
+use crate::paths;
+
+/// A contiguous range of ports reserved for one worktree
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PortBlock {
+    pub start: u16,
+    pub count: u16,
+}
+
+impl PortBlock {
+    pub fn end(&self) -> u16 {
+        self.start + self.count - 1
+    }
+
+    fn overlaps(&self, start: u16, count: u16) -> bool {
+        let (own_start, own_end) = (self.start as u32, self.start as u32 + self.count as u32);
+        let (start, end) = (start as u32, start as u32 + count as u32);
+        own_start < end && start < own_end
+    }
+
+    /// `MAESTRO_PORT` for the first port, `MAESTRO_PORT_1`, `MAESTRO_PORT_2`, ...
+    /// for the rest of the block
+    pub fn env_vars(&self) -> Vec<(String, String)> {
+        (0..self.count)
+            .map(|i| {
+                let name = if i == 0 {
+                    "MAESTRO_PORT".to_string()
+                } else {
+                    format!("MAESTRO_PORT_{}", i)
+                };
+                (name, (self.start + i).to_string())
+            })
+            .collect()
+    }
+}
+
+impl std::fmt::Display for PortBlock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.count == 1 {
+            write!(f, "{}", self.start)
+        } else {
+            write!(f, "{}-{}", self.start, self.end())
+        }
+    }
+}
+
+/// Port blocks handed out to worktrees, keyed by worktree path
+pub struct PortStore {
+    path: PathBuf,
+}
+
+impl PortStore {
+    pub fn new() -> Self {
+        Self {
+            path: paths::state_dir().join("ports.json"),
+        }
+    }
+
+    pub fn load(&self) -> BTreeMap<String, PortBlock> {
+        fs::read_to_string(&self.path)
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, blocks: &BTreeMap<String, PortBlock>) -> std::io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let json = serde_json::to_string_pretty(blocks)?;
+        fs::write(&self.path, json)
+    }
+
+    pub fn get(&self, worktree_path: &Path) -> Option<PortBlock> {
+        self.load().get(&*worktree_path.to_string_lossy()).copied()
+    }
+
+    /// Return the worktree's port block, allocating one on first use.
+    ///
+    /// A previously allocated block is kept as long as its size still matches
+    /// `count`, so ports stay stable across opens.
+    pub fn allocate(&self, worktree_path: &Path, base: u16, count: u16) -> std::io::Result<PortBlock> {
+        let key = worktree_path.to_string_lossy().to_string();
+        let mut blocks = self.load();
+
+        if let Some(block) = blocks.get(&key).filter(|b| b.count == count) {
+            return Ok(*block);
+        }
+
+        // Forget blocks of worktrees that have since been deleted
+        blocks.retain(|path, _| path != &key && Path::new(path).exists());
+
+        let start = find_free_start(blocks.values(), base, count, port_is_free).ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::Other, "No free port block available")
+        })?;
+        let block = PortBlock { start, count };
+        blocks.insert(key, block);
+        self.save(&blocks)?;
+        Ok(block)
+    }
+
+    pub fn release(&self, worktree_path: &Path) -> std::io::Result<()> {
+        let mut blocks = self.load();
+        if blocks.remove(&*worktree_path.to_string_lossy()).is_some() {
+            self.save(&blocks)?;
+        }
+        Ok(())
+    }
+}
+
+/// Find the lowest block start at or above `base` that doesn't overlap a
+/// reserved block and whose ports are all free
+fn find_free_start<'a>(
+    reserved: impl Iterator<Item = &'a PortBlock> + Clone,
+    base: u16,
+    count: u16,
+    is_free: impl Fn(u16) -> bool,
+) -> Option<u16> {
+    if count == 0 {
+        return None;
+    }
+
+    let mut start = base;
+    loop {
+        let end = start.checked_add(count - 1)?;
+        if let Some(block) = reserved.clone().find(|b| b.overlaps(start, count)) {
+            start = block.start.checked_add(block.count)?;
+            continue;
+        }
+        if let Some(busy) = (start..=end).find(|port| !is_free(*port)) {
+            start = busy.checked_add(1)?;
+            continue;
+        }
+        return Some(start);
+    }
+}
+
+fn port_is_free(port: u16) -> bool {
+    TcpListener::bind(("127.0.0.1", port)).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_free_start() {
+        let reserved = [
+            PortBlock { start: 20000, count: 10 },
+            PortBlock { start: 20010, count: 10 },
+            PortBlock { start: 20030, count: 10 },
+        ];
+
+        assert_eq!(find_free_start(reserved.iter(), 20000, 10, |_| true), Some(20020));
+        assert_eq!(find_free_start(reserved.iter(), 20000, 11, |_| true), Some(20040));
+        // Skip past ports that are taken by something else
+        assert_eq!(find_free_start(reserved.iter(), 20000, 5, |p| p != 20022), Some(20023));
+        assert_eq!(find_free_start(reserved.iter(), 65530, 10, |_| true), None);
+    }
+
+    #[test]
+    fn test_env_vars() {
+        let block = PortBlock { start: 3000, count: 3 };
+        assert_eq!(
+            block.env_vars(),
+            vec![
+                ("MAESTRO_PORT".to_string(), "3000".to_string()),
+                ("MAESTRO_PORT_1".to_string(), "3001".to_string()),
+                ("MAESTRO_PORT_2".to_string(), "3002".to_string()),
+            ]
+        );
+        assert_eq!(block.to_string(), "3000-3002");
+    }
+}
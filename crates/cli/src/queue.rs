@@ -0,0 +1,506 @@
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Error, ErrorKind};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::config::{main_worktree, Config};
+use crate::instance::{unix_now, Instance, InstanceStore, Status};
+use crate::worktree::{self, format_age};
+use crate::{paths, zellij, QueueCommands};
+
+const QUEUE_FILE: &str = "queue.json";
+/// Held while the queue is read and written back
+const LOCK_FILE: &str = "queue.lock";
+/// Dispatched prompts kept for `queue list --all`
+const KEEP_DISPATCHED: usize = 50;
+/// Seconds a worktree started for a prompt counts as a busy agent before its
+/// agent registers
+const STARTUP_GRACE: u64 = 120;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ItemStatus {
+    Pending,
+    Dispatched,
+}
+
+/// A prompt waiting for an agent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueueItem {
+    pub id: u32,
+    pub repo: String,
+    /// Main worktree of the repository; needed to start new worktrees
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repo_path: Option<PathBuf>,
+    pub prompt: String,
+    pub added_at: u64,
+    pub status: ItemStatus,
+    /// Pane of the idle agent the prompt was handed to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pane_id: Option<u32>,
+    /// Branch of the worktree started for the prompt
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dispatched_at: Option<u64>,
+}
+
+pub struct Queue {
+    path: PathBuf,
+}
+
+impl Queue {
+    pub fn new() -> Self {
+        Self {
+            path: paths::state_dir().join(QUEUE_FILE),
+        }
+    }
+
+    pub fn load(&self) -> Vec<QueueItem> {
+        fs::read_to_string(&self.path)
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    /// Save the queue, dropping the oldest dispatched prompts beyond
+    /// `KEEP_DISPATCHED`
+    pub fn save(&self, items: &[QueueItem]) -> io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_string_pretty(&trim_dispatched(items))?;
+        fs::write(&self.path, json)
+    }
+
+    /// Load, change and save the queue while holding `queue.lock`, so hooks
+    /// and dispatches running at the same time don't lose each other's changes
+    fn update<T>(&self, change: impl FnOnce(&mut Vec<QueueItem>) -> T) -> io::Result<T> {
+        let _lock = self.lock()?;
+        let mut items = self.load();
+        let result = change(&mut items);
+        self.save(&items)?;
+        Ok(result)
+    }
+
+    /// Exclusive lock on the queue, released when the file is dropped
+    fn lock(&self) -> io::Result<File> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.path.with_file_name(LOCK_FILE))?;
+        file.lock()?;
+        Ok(file)
+    }
+
+    pub fn add(
+        &self,
+        repo: String,
+        repo_path: Option<PathBuf>,
+        prompt: String,
+    ) -> io::Result<QueueItem> {
+        self.update(|items| {
+            let item = QueueItem {
+                id: items.iter().map(|i| i.id).max().unwrap_or(0) + 1,
+                repo,
+                repo_path,
+                prompt,
+                added_at: unix_now(),
+                status: ItemStatus::Pending,
+                pane_id: None,
+                branch: None,
+                dispatched_at: None,
+            };
+            items.push(item.clone());
+            item
+        })
+    }
+
+    /// Remove pending prompts, returning the ids that were found
+    pub fn cancel(&self, ids: &[u32]) -> io::Result<Vec<u32>> {
+        self.update(|items| {
+            let (cancelled, kept): (Vec<_>, Vec<_>) = std::mem::take(items)
+                .into_iter()
+                .partition(|i| i.status == ItemStatus::Pending && ids.contains(&i.id));
+            *items = kept;
+            cancelled.iter().map(|i| i.id).collect()
+        })
+    }
+
+    /// Take the oldest pending prompt for `repo`, as handed to `pane_id`
+    pub fn take(&self, repo: &str, pane_id: u32) -> io::Result<Option<QueueItem>> {
+        self.update(|items| {
+            let item = next_pending(items, repo)?;
+            item.status = ItemStatus::Dispatched;
+            item.pane_id = Some(pane_id);
+            item.dispatched_at = Some(unix_now());
+            Some(item.clone())
+        })
+    }
+
+    /// Put a prompt that couldn't be started back in the queue
+    pub fn requeue(&self, id: u32) -> io::Result<()> {
+        self.update(|items| {
+            if let Some(item) = items.iter_mut().find(|i| i.id == id) {
+                item.status = ItemStatus::Pending;
+                item.pane_id = None;
+                item.branch = None;
+                item.dispatched_at = None;
+            }
+        })
+    }
+}
+
+fn next_pending<'a>(items: &'a mut [QueueItem], repo: &str) -> Option<&'a mut QueueItem> {
+    items
+        .iter_mut()
+        .filter(|i| i.status == ItemStatus::Pending && i.repo == repo)
+        .min_by_key(|i| i.id)
+}
+
+fn trim_dispatched(items: &[QueueItem]) -> Vec<QueueItem> {
+    let dispatched = items
+        .iter()
+        .filter(|i| i.status == ItemStatus::Dispatched)
+        .count();
+    let mut excess = dispatched.saturating_sub(KEEP_DISPATCHED);
+    let mut by_age: Vec<&QueueItem> = items.iter().collect();
+    by_age.sort_by_key(|i| i.id);
+    by_age
+        .into_iter()
+        .filter(|i| {
+            if excess > 0 && i.status == ItemStatus::Dispatched {
+                excess -= 1;
+                return false;
+            }
+            true
+        })
+        .cloned()
+        .collect()
+}
+
+/// How many more agents `repo` can start: busy instances, and worktrees
+/// started for prompts whose agents haven't registered yet, count against
+/// `concurrency`. Idle agents don't.
+fn free_slots(
+    items: &[QueueItem],
+    instances: &[Instance],
+    repo: &str,
+    concurrency: usize,
+    now: u64,
+) -> usize {
+    let in_repo = |i: &&Instance| i.repo.as_deref() == Some(repo);
+    let busy = instances
+        .iter()
+        .filter(in_repo)
+        .filter(|i| i.status != Status::Waiting)
+        .count();
+    let starting = items
+        .iter()
+        .filter(|item| item.repo == repo)
+        .filter(|item| {
+            item.dispatched_at
+                .is_some_and(|at| now.saturating_sub(at) < STARTUP_GRACE)
+        })
+        .filter_map(|item| item.branch.as_deref())
+        .filter(|branch| {
+            !instances
+                .iter()
+                .filter(in_repo)
+                .any(|i| i.branch.as_deref() == Some(*branch))
+        })
+        .count();
+    concurrency.saturating_sub(busy + starting)
+}
+
+/// Hand the next queued prompt for the instance's repository to it. Prompts
+/// left over are started in new worktrees in the background.
+pub fn on_idle(instance: &Instance) -> Option<QueueItem> {
+    let repo = instance.repo.as_deref()?;
+    let queue = Queue::new();
+    let item = match queue.take(repo, instance.pane_id) {
+        Ok(item) => item?,
+        Err(e) => {
+            eprintln!("Warning: Could not read the queue: {}", e);
+            return None;
+        }
+    };
+    let more = queue
+        .load()
+        .iter()
+        .any(|i| i.status == ItemStatus::Pending && i.repo == repo);
+    if more {
+        schedule_dispatch(repo);
+    }
+    Some(item)
+}
+
+/// Stop hook output that makes Claude Code carry on with a queued prompt
+pub fn hook_response(item: &QueueItem) -> String {
+    serde_json::json!({
+        "decision": "block",
+        "reason": format!("Next task from the maestro queue: {}", item.prompt),
+    })
+    .to_string()
+}
+
+/// Start a detached `maestro queue dispatch`, so a hook doesn't wait for new
+/// worktrees to be set up
+fn schedule_dispatch(repo: &str) {
+    let Ok(exe) = std::env::current_exe() else {
+        return;
+    };
+    let _ = Command::new(exe)
+        .args(["queue", "dispatch", "--repo", repo])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn();
+}
+
+/// Start pending prompts for `repo` in new worktrees while there are free
+/// slots. Needs a Zellij session to open the worktrees' tabs in.
+fn dispatch(repo: &str) -> io::Result<()> {
+    if !zellij::inside_session() {
+        return Ok(());
+    }
+    let queue = Queue::new();
+    let Some(repo_path) = queue
+        .load()
+        .iter()
+        .filter(|i| i.status == ItemStatus::Pending && i.repo == repo)
+        .find_map(|i| i.repo_path.clone())
+    else {
+        return Ok(());
+    };
+    let concurrency = Config::load(&repo_path)?.queue.concurrency;
+    let instances = InstanceStore::new().load();
+    let now = unix_now();
+
+    // Claim the prompts under the queue lock before starting anything, so a
+    // concurrent dispatch doesn't start them twice
+    let started = queue.update(|items| {
+        let free = free_slots(items, &instances, repo, concurrency, now);
+        let mut started = Vec::new();
+        while started.len() < free {
+            let Some(item) = next_pending(items, repo) else {
+                break;
+            };
+            item.status = ItemStatus::Dispatched;
+            item.branch = Some(format!("queue-{}", item.id));
+            item.dispatched_at = Some(now);
+            started.push(item.clone());
+        }
+        started
+    })?;
+
+    for item in started {
+        let branch = item.branch.as_deref().expect("set above");
+        println!("Starting {} for queued prompt #{}", branch, item.id);
+        if let Err(e) = worktree::spawn_agent(&repo_path, branch, &item.prompt) {
+            eprintln!("Warning: Could not start {}: {}", branch, e);
+            queue.requeue(item.id)?;
+        }
+    }
+    Ok(())
+}
+
+/// Repository name and main worktree for `queue add`: the current repository,
+/// or the one named by `--repo`, found through its registered instances
+fn resolve_repo(name: Option<String>) -> io::Result<(String, Option<PathBuf>)> {
+    let cwd = std::env::current_dir()?;
+    let (current, _) = worktree::git_context(&cwd);
+    match name {
+        None => {
+            let repo = current.ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    "Not in a git repository; pass --repo",
+                )
+            })?;
+            Ok((repo, main_worktree(&cwd)))
+        }
+        Some(name) if current.as_deref() == Some(name.as_str()) => Ok((name, main_worktree(&cwd))),
+        Some(name) => {
+            let path = InstanceStore::new()
+                .load()
+                .iter()
+                .filter(|i| i.repo.as_deref() == Some(name.as_str()))
+                .find_map(|i| {
+                    i.cwd
+                        .as_deref()
+                        .and_then(|cwd| main_worktree(Path::new(cwd)))
+                });
+            Ok((name, path))
+        }
+    }
+}
+
+pub fn run(command: QueueCommands) -> io::Result<()> {
+    match command {
+        QueueCommands::Add { prompt, repo } => cmd_add(prompt, repo),
+        QueueCommands::List { all, json } => cmd_list(all, json),
+        QueueCommands::Cancel { ids } => cmd_cancel(&ids),
+        QueueCommands::Dispatch { repo } => dispatch(&repo),
+    }
+}
+
+fn cmd_add(prompt: String, repo: Option<String>) -> io::Result<()> {
+    let (repo, repo_path) = resolve_repo(repo)?;
+    let item = Queue::new().add(repo.clone(), repo_path, prompt)?;
+    println!("Queued #{} for {}", item.id, repo);
+    if item.repo_path.is_none() {
+        println!(
+            "No checkout of {} known; the prompt waits for an idle agent",
+            repo
+        );
+    }
+    dispatch(&repo)
+}
+
+fn cmd_list(all: bool, json: bool) -> io::Result<()> {
+    let items: Vec<QueueItem> = Queue::new()
+        .load()
+        .into_iter()
+        .filter(|i| all || i.status == ItemStatus::Pending)
+        .collect();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&items)?);
+        return Ok(());
+    }
+    if items.is_empty() {
+        println!("No queued prompts");
+        return Ok(());
+    }
+
+    let now = unix_now();
+    let repo_width = items
+        .iter()
+        .map(|i| i.repo.chars().count())
+        .max()
+        .unwrap_or(0);
+    for item in &items {
+        let state = match (item.status, item.pane_id, &item.branch) {
+            (ItemStatus::Pending, _, _) => "pending".to_string(),
+            (ItemStatus::Dispatched, Some(pane_id), _) => format!("→ pane {}", pane_id),
+            (ItemStatus::Dispatched, None, Some(branch)) => format!("→ {}", branch),
+            (ItemStatus::Dispatched, None, None) => "dispatched".to_string(),
+        };
+        let prompt = item.prompt.lines().next().unwrap_or_default();
+        println!(
+            "#{:<4} {:<repo_width$}  {:<12} {:<9} {}",
+            item.id,
+            item.repo,
+            state,
+            format_age(now.saturating_sub(item.added_at)),
+            prompt,
+        );
+    }
+    Ok(())
+}
+
+fn cmd_cancel(ids: &[u32]) -> io::Result<()> {
+    let cancelled = Queue::new().cancel(ids)?;
+    for id in ids {
+        if cancelled.contains(id) {
+            println!("Cancelled #{}", id);
+        } else {
+            eprintln!("No pending prompt #{}", id);
+        }
+    }
+    if cancelled.is_empty() {
+        return Err(Error::new(ErrorKind::NotFound, "Nothing cancelled"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(id: u32, repo: &str, status: ItemStatus) -> QueueItem {
+        QueueItem {
+            id,
+            repo: repo.to_string(),
+            repo_path: None,
+            prompt: format!("task {}", id),
+            added_at: 0,
+            status,
+            pane_id: None,
+            branch: None,
+            dispatched_at: None,
+        }
+    }
+
+    fn instance(repo: &str, branch: &str, status: Status) -> Instance {
+        Instance::for_test(&format!("{}.{}", repo, branch), Some(repo), Some(branch), status)
+    }
+
+    #[test]
+    fn test_next_pending() {
+        let mut items = vec![
+            item(1, "app", ItemStatus::Dispatched),
+            item(3, "app", ItemStatus::Pending),
+            item(2, "lib", ItemStatus::Pending),
+            item(4, "app", ItemStatus::Pending),
+        ];
+        assert_eq!(next_pending(&mut items, "app").map(|i| i.id), Some(3));
+        assert_eq!(next_pending(&mut items, "lib").map(|i| i.id), Some(2));
+        assert!(next_pending(&mut items, "other").is_none());
+    }
+
+    #[test]
+    fn test_free_slots() {
+        let instances = vec![
+            instance("app", "a", Status::Running),
+            instance("app", "b", Status::Waiting),
+            instance("app", "c", Status::Permission),
+            instance("lib", "d", Status::Running),
+        ];
+        assert_eq!(free_slots(&[], &instances, "app", 3, 1000), 1);
+        assert_eq!(free_slots(&[], &instances, "app", 2, 1000), 0);
+
+        // Started a minute ago and not registered yet
+        let mut starting = item(1, "app", ItemStatus::Dispatched);
+        starting.branch = Some("queue-1".to_string());
+        starting.dispatched_at = Some(940);
+        // Registered, so already counted if busy
+        let mut registered = starting.clone();
+        registered.branch = Some("a".to_string());
+        // Started long ago and never registered
+        let mut stale = starting.clone();
+        stale.dispatched_at = Some(100);
+        let items = [starting, registered, stale];
+        assert_eq!(free_slots(&items, &instances, "app", 4, 1000), 1);
+    }
+
+    #[test]
+    fn test_trim_dispatched() {
+        let mut items: Vec<QueueItem> = (1..=KEEP_DISPATCHED as u32 + 2)
+            .map(|id| item(id, "app", ItemStatus::Dispatched))
+            .collect();
+        items.insert(0, item(100, "app", ItemStatus::Pending));
+        let trimmed = trim_dispatched(&items);
+        assert_eq!(trimmed.len(), KEEP_DISPATCHED + 1);
+        assert_eq!(trimmed[0].id, 3);
+        assert!(trimmed.iter().any(|i| i.id == 100));
+    }
+
+    #[test]
+    fn test_hook_response() {
+        let response: serde_json::Value =
+            serde_json::from_str(&hook_response(&item(7, "app", ItemStatus::Dispatched))).unwrap();
+        assert_eq!(response["decision"], "block");
+        assert_eq!(
+            response["reason"],
+            "Next task from the maestro queue: task 7"
+        );
+    }
+}
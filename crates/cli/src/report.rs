@@ -0,0 +1,543 @@
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io;
+
+use crate::eventlog::{format_timestamp, EventLog, LogEntry};
+use crate::instance::{unix_now, InstanceStore, Status};
+use crate::transcript::{format_tokens, TokenUsage};
+
+/// Tools listed per worktree in text and Markdown reports
+const TOP_TOOLS: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum ReportFormat {
+    #[default]
+    Text,
+    Json,
+    Markdown,
+}
+
+/// Activity of the agents in one worktree, repo, or overall
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Stats {
+    /// Seconds agents were working
+    pub running_secs: u64,
+    /// Seconds agents were blocked waiting for input or permission
+    pub waiting_secs: u64,
+    pub sessions: usize,
+    /// Tool calls by tool name
+    pub tools: BTreeMap<String, u64>,
+    pub tokens: TokenUsage,
+    pub cost_usd: f64,
+}
+
+impl Stats {
+    fn add(&mut self, other: &Stats) {
+        self.running_secs += other.running_secs;
+        self.waiting_secs += other.waiting_secs;
+        self.sessions += other.sessions;
+        for (tool, count) in &other.tools {
+            *self.tools.entry(tool.clone()).or_default() += count;
+        }
+        self.tokens += other.tokens;
+        self.cost_usd += other.cost_usd;
+    }
+
+    /// Most used tools, most calls first
+    fn top_tools(&self, n: usize) -> Vec<(&str, u64)> {
+        let mut tools: Vec<_> = self.tools.iter().map(|(t, c)| (t.as_str(), *c)).collect();
+        tools.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        tools.truncate(n);
+        tools
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct WorktreeReport {
+    /// Folder of the worktree
+    pub worktree: String,
+    pub branch: Option<String>,
+    #[serde(flatten)]
+    pub stats: Stats,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RepoReport {
+    pub repo: String,
+    #[serde(flatten)]
+    pub stats: Stats,
+    pub worktrees: Vec<WorktreeReport>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub since: u64,
+    pub until: u64,
+    pub totals: Stats,
+    pub repos: Vec<RepoReport>,
+}
+
+/// Repo and worktree folder an event belongs to
+type Key = (String, String);
+
+/// What an instance was doing since its last event
+struct PaneState {
+    status: Status,
+    since: u64,
+    key: Key,
+    session: String,
+}
+
+#[derive(Default)]
+struct Session {
+    key: Key,
+    /// Whether the session did anything between `since` and `until`
+    active: bool,
+    /// Last token and cost totals before `since`
+    before: Option<(TokenUsage, f64)>,
+    /// Last token and cost totals up to `until`
+    latest: Option<(TokenUsage, f64)>,
+}
+
+fn key(entry: &LogEntry) -> Key {
+    let repo = entry
+        .repo
+        .clone()
+        .unwrap_or_else(|| "(no repo)".to_string());
+    let worktree = entry
+        .instance
+        .clone()
+        .or_else(|| entry.cwd.clone())
+        .unwrap_or_else(|| format!("pane {}", entry.pane_id));
+    (repo, worktree)
+}
+
+/// Summarise the events between `since` and `until`. Instances in `live` are
+/// still running, so their last status lasts until `until`; the others went
+/// away without an event and are not counted past their last one.
+pub fn build(entries: &[LogEntry], since: u64, until: u64, live: &HashSet<u32>) -> Report {
+    let mut stats: BTreeMap<Key, Stats> = BTreeMap::new();
+    let mut branches: HashMap<Key, String> = HashMap::new();
+    let mut panes: HashMap<u32, PaneState> = HashMap::new();
+    let mut sessions: HashMap<String, Session> = HashMap::new();
+
+    // Time in a status from `start` to `end`, clipped to the report window
+    let spend = |stats: &mut BTreeMap<Key, Stats>,
+                 sessions: &mut HashMap<String, Session>,
+                 pane: &PaneState,
+                 end: u64| {
+        let secs = end.min(until).saturating_sub(pane.since.max(since));
+        if secs == 0 {
+            return;
+        }
+        let entry = stats.entry(pane.key.clone()).or_default();
+        match pane.status {
+            Status::Running => entry.running_secs += secs,
+            Status::Waiting | Status::Permission => entry.waiting_secs += secs,
+        }
+        let session = sessions.entry(pane.session.clone()).or_default();
+        session.key = pane.key.clone();
+        session.active = true;
+    };
+
+    let mut entries: Vec<&LogEntry> = entries.iter().collect();
+    entries.sort_by_key(|e| e.timestamp);
+    for entry in entries {
+        if entry.timestamp > until {
+            break;
+        }
+        let key = key(entry);
+        let previous = panes.remove(&entry.pane_id);
+        if let Some(pane) = &previous {
+            spend(&mut stats, &mut sessions, pane, entry.timestamp);
+        }
+        let session_id = entry
+            .session_id
+            .clone()
+            .or_else(|| previous.map(|p| p.session))
+            .unwrap_or_else(|| format!("pane {} at {}", entry.pane_id, entry.timestamp));
+
+        if let Some(branch) = &entry.branch {
+            branches.insert(key.clone(), branch.clone());
+        }
+
+        let session = sessions.entry(session_id.clone()).or_default();
+        session.key = key.clone();
+        if let Some(tokens) = entry.tokens {
+            let totals = (tokens, entry.cost_usd.unwrap_or(0.0));
+            if entry.timestamp < since {
+                session.before = Some(totals);
+            } else {
+                session.latest = Some(totals);
+            }
+        }
+        if entry.timestamp >= since {
+            session.active = true;
+            if entry.event == "PreToolUse"
+                && let Some(tool) = &entry.tool
+            {
+                let stats = stats.entry(key.clone()).or_default();
+                *stats.tools.entry(tool.clone()).or_default() += 1;
+            }
+        }
+
+        if let Some(status) = entry.status {
+            panes.insert(
+                entry.pane_id,
+                PaneState {
+                    status,
+                    since: entry.timestamp,
+                    key,
+                    session: session_id,
+                },
+            );
+        }
+    }
+    for (pane_id, pane) in &panes {
+        if live.contains(pane_id) {
+            spend(&mut stats, &mut sessions, pane, until);
+        }
+    }
+
+    for session in sessions.values().filter(|s| s.active) {
+        let stats = stats.entry(session.key.clone()).or_default();
+        stats.sessions += 1;
+        if let Some((tokens, cost)) = session.latest {
+            let (before_tokens, before_cost) = session.before.unwrap_or_default();
+            stats.tokens += tokens.since(&before_tokens);
+            stats.cost_usd += (cost - before_cost).max(0.0);
+        }
+    }
+
+    let mut totals = Stats::default();
+    let mut repos: Vec<RepoReport> = Vec::new();
+    for ((repo, worktree), stats) in stats {
+        totals.add(&stats);
+        if repos.last().is_none_or(|r| r.repo != repo) {
+            repos.push(RepoReport {
+                repo: repo.clone(),
+                stats: Stats::default(),
+                worktrees: Vec::new(),
+            });
+        }
+        let report = repos.last_mut().expect("pushed above");
+        report.stats.add(&stats);
+        report.worktrees.push(WorktreeReport {
+            branch: branches.remove(&(repo, worktree.clone())),
+            worktree,
+            stats,
+        });
+    }
+
+    Report {
+        since,
+        until,
+        totals,
+        repos,
+    }
+}
+
+/// Print a report of the last `since` seconds
+pub fn cmd_report(since: u64, format: ReportFormat) -> io::Result<()> {
+    let until = unix_now();
+    let live: HashSet<u32> = InstanceStore::new()
+        .load()
+        .iter()
+        .map(|i| i.pane_id)
+        .collect();
+    let report = build(
+        &EventLog::new().read_all(),
+        until.saturating_sub(since),
+        until,
+        &live,
+    );
+    match format {
+        ReportFormat::Text => print!("{}", text(&report)),
+        ReportFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+        ReportFormat::Markdown => print!("{}", markdown(&report)),
+    }
+    Ok(())
+}
+
+/// Format seconds as e.g. `2h 05m`, `41m` or `12s`
+pub fn format_duration(secs: u64) -> String {
+    match secs {
+        0..60 => format!("{}s", secs),
+        60..3600 => format!("{}m", secs / 60),
+        _ => format!("{}h {:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+fn tools_list(stats: &Stats) -> String {
+    stats
+        .top_tools(TOP_TOOLS)
+        .iter()
+        .map(|(tool, count)| format!("{} {}", tool, count))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn summary(stats: &Stats) -> String {
+    format!(
+        "running {}, waiting {}, {} session{}, {} in / {} out, ${:.2}",
+        format_duration(stats.running_secs),
+        format_duration(stats.waiting_secs),
+        stats.sessions,
+        if stats.sessions == 1 { "" } else { "s" },
+        format_tokens(stats.tokens.total_input()),
+        format_tokens(stats.tokens.output),
+        stats.cost_usd
+    )
+}
+
+fn text(report: &Report) -> String {
+    let mut out = format!("Agent activity since {}\n", format_timestamp(report.since));
+    if report.repos.is_empty() {
+        out.push_str("\nNo activity recorded.\n");
+        return out;
+    }
+    for repo in &report.repos {
+        out.push_str(&format!("\n{}: {}\n", repo.repo, summary(&repo.stats)));
+        for worktree in &repo.worktrees {
+            let branch = worktree
+                .branch
+                .as_ref()
+                .map(|b| format!(" ({})", b))
+                .unwrap_or_default();
+            out.push_str(&format!("  {}{}\n", worktree.worktree, branch));
+            out.push_str(&format!("    {}\n", summary(&worktree.stats)));
+            if !worktree.stats.tools.is_empty() {
+                out.push_str(&format!("    tools: {}\n", tools_list(&worktree.stats)));
+            }
+        }
+    }
+    if report.repos.len() > 1 {
+        out.push_str(&format!("\nTotal: {}\n", summary(&report.totals)));
+    }
+    out
+}
+
+fn markdown_row(repo: &str, worktree: &str, stats: &Stats) -> String {
+    let escape = |s: &str| s.replace('|', "\\|");
+    format!(
+        "| {} | {} | {} | {} | {} | {} | {} | ${:.2} | {} |\n",
+        escape(repo),
+        escape(worktree),
+        format_duration(stats.running_secs),
+        format_duration(stats.waiting_secs),
+        stats.sessions,
+        format_tokens(stats.tokens.total_input()),
+        format_tokens(stats.tokens.output),
+        stats.cost_usd,
+        escape(&tools_list(stats))
+    )
+}
+
+fn markdown(report: &Report) -> String {
+    let mut out = format!(
+        "# Agent activity since {}\n\n",
+        format_timestamp(report.since)
+    );
+    if report.repos.is_empty() {
+        out.push_str("No activity recorded.\n");
+        return out;
+    }
+    out.push_str(
+        "| Repo | Worktree | Running | Waiting | Sessions | Tokens in | Tokens out | Cost | Top tools |\n",
+    );
+    out.push_str("|---|---|---:|---:|---:|---:|---:|---:|---|\n");
+    for repo in &report.repos {
+        for worktree in &repo.worktrees {
+            let name = match &worktree.branch {
+                Some(branch) => format!("{} ({})", worktree.worktree, branch),
+                None => worktree.worktree.clone(),
+            };
+            out.push_str(&markdown_row(&repo.repo, &name, &worktree.stats));
+        }
+        if repo.worktrees.len() > 1 {
+            out.push_str(&markdown_row(&repo.repo, "**all**", &repo.stats));
+        }
+    }
+    if report.repos.len() > 1 {
+        out.push_str(&markdown_row("**Total**", "", &report.totals));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(timestamp: u64, pane_id: u32, event: &str, status: Option<Status>) -> LogEntry {
+        LogEntry {
+            timestamp,
+            event: event.to_string(),
+            pane_id,
+            session_id: Some(format!("s{}", pane_id)),
+            instance: Some(format!("app.wt-{}", pane_id)),
+            repo: Some("app".to_string()),
+            branch: Some(format!("wt-{}", pane_id)),
+            cwd: None,
+            status,
+            tool: None,
+            tokens: None,
+            cost_usd: None,
+        }
+    }
+
+    fn tool(timestamp: u64, pane_id: u32, name: &str) -> LogEntry {
+        let mut entry = entry(timestamp, pane_id, "PreToolUse", Some(Status::Running));
+        entry.tool = Some(name.to_string());
+        entry
+    }
+
+    fn usage(mut entry: LogEntry, input: u64, cost: f64) -> LogEntry {
+        entry.tokens = Some(TokenUsage {
+            input,
+            ..Default::default()
+        });
+        entry.cost_usd = Some(cost);
+        entry
+    }
+
+    #[test]
+    fn test_build_times() {
+        let entries = vec![
+            // Running from before the window, which starts at 100
+            entry(50, 1, "SessionStart", Some(Status::Running)),
+            entry(150, 1, "Stop", Some(Status::Waiting)),
+            entry(170, 1, "UserPromptSubmit", Some(Status::Running)),
+            entry(200, 1, "SessionEnd", None),
+            // Still running at the end of the window
+            entry(180, 2, "SessionStart", Some(Status::Permission)),
+            // Gone without an event; not counted past it
+            entry(190, 3, "SessionStart", Some(Status::Running)),
+        ];
+        let live = HashSet::from([2]);
+        let report = build(&entries, 100, 300, &live);
+
+        assert_eq!(report.repos.len(), 1);
+        let repo = &report.repos[0];
+        assert_eq!(repo.repo, "app");
+        let worktrees: Vec<_> = repo
+            .worktrees
+            .iter()
+            .map(|w| {
+                (
+                    w.worktree.as_str(),
+                    w.stats.running_secs,
+                    w.stats.waiting_secs,
+                )
+            })
+            .collect();
+        assert_eq!(
+            worktrees,
+            [
+                ("app.wt-1", 80, 20),
+                ("app.wt-2", 0, 120),
+                ("app.wt-3", 0, 0)
+            ]
+        );
+        assert_eq!(repo.worktrees[0].branch.as_deref(), Some("wt-1"));
+        assert_eq!(repo.stats.running_secs, 80);
+        assert_eq!(repo.stats.waiting_secs, 140);
+        assert_eq!(repo.stats.sessions, 3);
+        assert_eq!(report.totals, repo.stats);
+    }
+
+    #[test]
+    fn test_build_tools_and_tokens() {
+        let entries = vec![
+            usage(
+                entry(50, 1, "SessionStart", Some(Status::Running)),
+                1000,
+                1.0,
+            ),
+            tool(60, 1, "Bash"),
+            tool(110, 1, "Bash"),
+            tool(120, 1, "Edit"),
+            usage(
+                entry(130, 1, "PostToolUse", Some(Status::Running)),
+                1500,
+                1.5,
+            ),
+            usage(entry(140, 1, "Stop", Some(Status::Waiting)), 4000, 2.0),
+            // Outside the window
+            tool(400, 1, "Read"),
+            usage(entry(400, 1, "Stop", Some(Status::Waiting)), 9000, 9.0),
+        ];
+        let report = build(&entries, 100, 300, &HashSet::new());
+        let stats = &report.totals;
+        assert_eq!(
+            stats.tools,
+            BTreeMap::from([("Bash".into(), 1), ("Edit".into(), 1)])
+        );
+        assert_eq!(stats.tokens.input, 3000);
+        assert!((stats.cost_usd - 1.0).abs() < 1e-9);
+        assert_eq!(stats.sessions, 1);
+    }
+
+    #[test]
+    fn test_build_sessions() {
+        let mut cleared = entry(150, 1, "SessionStart", Some(Status::Running));
+        cleared.session_id = Some("s1-cleared".to_string());
+        let mut hookless = entry(120, 2, "register", Some(Status::Running));
+        hookless.session_id = None;
+        let mut hookless_status = entry(130, 2, "status", Some(Status::Waiting));
+        hookless_status.session_id = None;
+        let entries = vec![
+            entry(110, 1, "SessionStart", Some(Status::Running)),
+            cleared,
+            hookless,
+            hookless_status,
+            // An old session that ended before the window
+            entry(10, 3, "SessionStart", Some(Status::Running)),
+            entry(20, 3, "SessionEnd", None),
+        ];
+        let report = build(&entries, 100, 300, &HashSet::new());
+        let sessions: Vec<_> = report.repos[0]
+            .worktrees
+            .iter()
+            .map(|w| (w.worktree.as_str(), w.stats.sessions))
+            .collect();
+        assert_eq!(sessions, [("app.wt-1", 2), ("app.wt-2", 1)]);
+    }
+
+    #[test]
+    fn test_top_tools() {
+        let stats = Stats {
+            tools: BTreeMap::from([
+                ("Bash".into(), 3),
+                ("Edit".into(), 5),
+                ("Grep".into(), 3),
+                ("Read".into(), 1),
+            ]),
+            ..Default::default()
+        };
+        assert_eq!(stats.top_tools(3), [("Edit", 5), ("Bash", 3), ("Grep", 3)]);
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(12), "12s");
+        assert_eq!(format_duration(41 * 60 + 5), "41m");
+        assert_eq!(format_duration(2 * 3600 + 5 * 60), "2h 05m");
+    }
+
+    #[test]
+    fn test_markdown() {
+        let entries = vec![
+            tool(110, 1, "Bash"),
+            entry(120, 1, "Stop", Some(Status::Waiting)),
+        ];
+        let report = build(&entries, 100, 130, &HashSet::from([1]));
+        let markdown = markdown(&report);
+        assert!(markdown.starts_with("# Agent activity since 1970-01-01 00:01:40Z\n"));
+        assert!(markdown
+            .contains("| app | app.wt-1 (wt-1) | 10s | 10s | 1 | 0 | 0 | $0.00 | Bash 1 |\n"));
+
+        let empty = build(&[], 100, 130, &HashSet::new());
+        assert!(text(&empty).ends_with("No activity recorded.\n"));
+    }
+}
@@ -0,0 +1,301 @@
+use crate::config::CacheFallback;
+use std::fs;
+use std::io::{self, Error, ErrorKind};
+use std::path::{Component, Path, PathBuf};
+
+/// Copy files matching `patterns` from `source` into `dest`.
+///
+/// Directories are copied recursively. Paths that already exist in `dest` are
+/// left untouched. Returns the relative paths that were copied.
+pub fn copy_matching(source: &Path, dest: &Path, patterns: &[String]) -> io::Result<Vec<PathBuf>> {
+    let mut copied = Vec::new();
+    for rel in matching_paths(source, patterns)? {
+        let target = dest.join(&rel);
+        if target.symlink_metadata().is_ok() {
+            continue;
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        copy_recursive(&source.join(&rel), &target)?;
+        copied.push(rel);
+    }
+    Ok(copied)
+}
+
+/// Symlink files matching `patterns` in `dest` to their counterparts in `source`.
+///
+/// Paths that already exist in `dest` are left untouched. Returns the relative
+/// paths that were linked.
+pub fn symlink_matching(source: &Path, dest: &Path, patterns: &[String]) -> io::Result<Vec<PathBuf>> {
+    let mut linked = Vec::new();
+    for rel in matching_paths(source, patterns)? {
+        let target = dest.join(&rel);
+        if target.symlink_metadata().is_ok() {
+            continue;
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        std::os::unix::fs::symlink(source.join(&rel), &target)?;
+        linked.push(rel);
+    }
+    Ok(linked)
+}
+
+/// How the files of a seeded directory ended up in the new worktree
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SeedStats {
+    pub reflinked: usize,
+    pub hardlinked: usize,
+    pub copied: usize,
+}
+
+impl std::fmt::Display for SeedStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let parts: Vec<String> = [
+            (self.reflinked, "reflinked"),
+            (self.hardlinked, "hardlinked"),
+            (self.copied, "copied"),
+        ]
+        .iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, how)| format!("{} {}", count, how))
+        .collect();
+
+        if parts.is_empty() {
+            write!(f, "empty")
+        } else {
+            write!(f, "{}", parts.join(", "))
+        }
+    }
+}
+
+/// Seed cache directories (e.g. `node_modules`) matching `dirs` from `source`
+/// into `dest`, using copy-on-write reflinks where the filesystem supports
+/// them and `fallback` otherwise.
+///
+/// Directories that already exist in `dest` are left untouched.
+pub fn seed_cache(
+    source: &Path,
+    dest: &Path,
+    dirs: &[String],
+    fallback: CacheFallback,
+) -> io::Result<Vec<(PathBuf, SeedStats)>> {
+    let mut seeder = Seeder {
+        fallback,
+        reflink_supported: true,
+    };
+    let mut seeded = Vec::new();
+
+    for rel in matching_paths(source, dirs)? {
+        let from = source.join(&rel);
+        let target = dest.join(&rel);
+        if !from.is_dir() || target.symlink_metadata().is_ok() {
+            continue;
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut stats = SeedStats::default();
+        seeder.seed_dir(&from, &target, &mut stats)?;
+        seeded.push((rel, stats));
+    }
+
+    Ok(seeded)
+}
+
+struct Seeder {
+    fallback: CacheFallback,
+    /// Cleared after the first failed reflink so we don't retry on every file
+    reflink_supported: bool,
+}
+
+impl Seeder {
+    fn seed_dir(&mut self, source: &Path, dest: &Path, stats: &mut SeedStats) -> io::Result<()> {
+        fs::create_dir_all(dest)?;
+        for entry in fs::read_dir(source)? {
+            let entry = entry?;
+            let from = entry.path();
+            let to = dest.join(entry.file_name());
+            let file_type = entry.file_type()?;
+
+            if file_type.is_symlink() {
+                std::os::unix::fs::symlink(fs::read_link(&from)?, &to)?;
+            } else if file_type.is_dir() {
+                self.seed_dir(&from, &to, stats)?;
+            } else {
+                self.seed_file(&from, &to, stats)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn seed_file(&mut self, from: &Path, to: &Path, stats: &mut SeedStats) -> io::Result<()> {
+        if self.reflink_supported {
+            if reflink_copy::reflink(from, to).is_ok() {
+                stats.reflinked += 1;
+                return Ok(());
+            }
+            self.reflink_supported = false;
+        }
+
+        // Hardlinks can't cross filesystems, so fall back to copying there too
+        if self.fallback == CacheFallback::Hardlink && fs::hard_link(from, to).is_ok() {
+            stats.hardlinked += 1;
+            return Ok(());
+        }
+
+        fs::copy(from, to)?;
+        stats.copied += 1;
+        Ok(())
+    }
+}
+
+/// Resolve glob `patterns` against `root`, returning matches relative to it
+fn matching_paths(root: &Path, patterns: &[String]) -> io::Result<Vec<PathBuf>> {
+    let escaped_root = glob::Pattern::escape(&root.to_string_lossy());
+    let mut paths = Vec::new();
+
+    for pattern in patterns {
+        let escapes_root = Path::new(pattern)
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir));
+        if escapes_root {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Pattern '{}' must be relative to the worktree root", pattern),
+            ));
+        }
+
+        let full_pattern = format!("{}/{}", escaped_root, pattern);
+        let entries = glob::glob(&full_pattern).map_err(|e| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("Invalid pattern '{}': {}", pattern, e),
+            )
+        })?;
+
+        for entry in entries.flatten() {
+            if let Ok(rel) = entry.strip_prefix(root)
+                && !rel.as_os_str().is_empty()
+                && !paths.iter().any(|p| p == rel)
+            {
+                paths.push(rel.to_path_buf());
+            }
+        }
+    }
+
+    Ok(paths)
+}
+
+fn copy_recursive(source: &Path, dest: &Path) -> io::Result<()> {
+    let meta = source.symlink_metadata()?;
+    if meta.file_type().is_symlink() {
+        std::os::unix::fs::symlink(fs::read_link(source)?, dest)
+    } else if meta.is_dir() {
+        fs::create_dir_all(dest)?;
+        for entry in fs::read_dir(source)? {
+            let entry = entry?;
+            copy_recursive(&entry.path(), &dest.join(entry.file_name()))?;
+        }
+        Ok(())
+    } else {
+        fs::copy(source, dest).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("maestro-seed-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_copy_and_symlink_matching() {
+        let root = temp_dir("copy");
+        let source = root.join("main");
+        let dest = root.join("feature");
+        fs::create_dir_all(source.join("certs")).unwrap();
+        fs::create_dir_all(&dest).unwrap();
+        fs::write(source.join(".env"), "SECRET=1").unwrap();
+        fs::write(source.join("certs/dev.pem"), "cert").unwrap();
+        fs::write(source.join("certs/notes.txt"), "notes").unwrap();
+        fs::write(source.join(".env.local"), "LOCAL=1").unwrap();
+        fs::write(dest.join(".env"), "EXISTING=1").unwrap();
+
+        let copied = copy_matching(
+            &source,
+            &dest,
+            &[".env".to_string(), "certs/*.pem".to_string()],
+        )
+        .unwrap();
+        assert_eq!(copied, vec![PathBuf::from("certs/dev.pem")]);
+        assert_eq!(fs::read_to_string(dest.join(".env")).unwrap(), "EXISTING=1");
+        assert!(!dest.join("certs/notes.txt").exists());
+
+        let linked = symlink_matching(&source, &dest, &[".env.local".to_string()]).unwrap();
+        assert_eq!(linked, vec![PathBuf::from(".env.local")]);
+        assert_eq!(fs::read_link(dest.join(".env.local")).unwrap(), source.join(".env.local"));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_seed_cache() {
+        use std::os::unix::fs::MetadataExt;
+
+        let root = temp_dir("cache");
+        let source = root.join("main");
+        let dest = root.join("feature");
+        fs::create_dir_all(source.join("node_modules/pkg")).unwrap();
+        fs::create_dir_all(&dest).unwrap();
+        fs::write(source.join("node_modules/pkg/index.js"), "module.exports = 1").unwrap();
+        fs::write(source.join("node_modules/.lock"), "").unwrap();
+        std::os::unix::fs::symlink("pkg/index.js", source.join("node_modules/entry")).unwrap();
+
+        let seeded = seed_cache(
+            &source,
+            &dest,
+            &["node_modules".to_string(), "target".to_string()],
+            CacheFallback::Hardlink,
+        )
+        .unwrap();
+
+        assert_eq!(seeded.len(), 1);
+        let (rel, stats) = &seeded[0];
+        assert_eq!(rel, &PathBuf::from("node_modules"));
+        assert_eq!(stats.reflinked + stats.hardlinked + stats.copied, 2);
+
+        let seeded_file = dest.join("node_modules/pkg/index.js");
+        assert_eq!(fs::read_to_string(&seeded_file).unwrap(), "module.exports = 1");
+        if stats.hardlinked == 2 {
+            let original = fs::metadata(source.join("node_modules/pkg/index.js")).unwrap();
+            assert_eq!(fs::metadata(&seeded_file).unwrap().ino(), original.ino());
+        }
+        assert_eq!(
+            fs::read_link(dest.join("node_modules/entry")).unwrap(),
+            PathBuf::from("pkg/index.js")
+        );
+
+        // Existing directories are left alone
+        let seeded = seed_cache(&source, &dest, &["node_modules".to_string()], CacheFallback::Copy).unwrap();
+        assert!(seeded.is_empty());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_rejects_patterns_outside_root() {
+        let root = temp_dir("escape");
+        assert!(copy_matching(&root, &root, &["../secrets".to_string()]).is_err());
+        assert!(copy_matching(&root, &root, &["/etc/passwd".to_string()]).is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
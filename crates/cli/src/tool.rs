@@ -0,0 +1,101 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+
+/// Longest tool description kept, in characters
+const MAX_DESCRIPTION: usize = 80;
+
+/// A tool call reported by a `PreToolUse` hook
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+    /// Tool name, e.g. `Bash` or `mcp__github__create_issue`
+    pub name: String,
+    /// Short description, e.g. `Bash: cargo test` or `Edit: src/lib.rs`
+    pub description: String,
+    /// Unix timestamp of the call
+    pub started_at: u64,
+}
+
+impl ToolCall {
+    pub fn new(name: &str, input: Option<&Value>, cwd: Option<&str>, started_at: u64) -> Self {
+        ToolCall {
+            name: name.to_string(),
+            description: describe(name, input, cwd),
+            started_at,
+        }
+    }
+}
+
+/// Describe a tool call by its most telling argument. Paths inside `cwd` are
+/// shown relative to it.
+pub fn describe(name: &str, input: Option<&Value>, cwd: Option<&str>) -> String {
+    let field = |key: &str| input.and_then(|i| i.get(key)).and_then(Value::as_str);
+    let detail = match name {
+        "Bash" => field("command").map(str::to_string),
+        "Read" | "Write" | "Edit" | "MultiEdit" => field("file_path").map(|p| relative(p, cwd)),
+        "NotebookEdit" => field("notebook_path").map(|p| relative(p, cwd)),
+        "Glob" | "Grep" => field("pattern").map(str::to_string),
+        "WebFetch" => field("url").map(str::to_string),
+        "WebSearch" => field("query").map(str::to_string),
+        "Task" => field("description").map(str::to_string),
+        _ => None,
+    };
+    let description = match detail {
+        Some(detail) => format!("{}: {}", name, first_line(&detail)),
+        None => name.to_string(),
+    };
+    truncate(&description, MAX_DESCRIPTION)
+}
+
+fn relative(path: &str, cwd: Option<&str>) -> String {
+    cwd.and_then(|cwd| Path::new(path).strip_prefix(cwd).ok())
+        .filter(|p| !p.as_os_str().is_empty())
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.to_string())
+}
+
+fn first_line(text: &str) -> &str {
+    text.trim().lines().next().unwrap_or_default()
+}
+
+fn truncate(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    let mut cut: String = text.chars().take(max - 1).collect();
+    cut.push('…');
+    cut
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_describe() {
+        let cwd = Some("/src/app");
+        let cases = [
+            ("Bash", json!({"command": "cargo test\n  --all"}), "Bash: cargo test"),
+            ("Edit", json!({"file_path": "/src/app/src/lib.rs"}), "Edit: src/lib.rs"),
+            ("Read", json!({"file_path": "/etc/hosts"}), "Read: /etc/hosts"),
+            ("Grep", json!({"pattern": "fn main"}), "Grep: fn main"),
+            ("WebFetch", json!({"url": "https://example.com"}), "WebFetch: https://example.com"),
+            ("Task", json!({"description": "Find callers"}), "Task: Find callers"),
+            ("TodoWrite", json!({"todos": []}), "TodoWrite"),
+            ("mcp__github__create_issue", json!({"title": "x"}), "mcp__github__create_issue"),
+        ];
+        for (name, input, expected) in cases {
+            assert_eq!(describe(name, Some(&input), cwd), expected);
+        }
+        assert_eq!(describe("Bash", None, cwd), "Bash");
+    }
+
+    #[test]
+    fn test_describe_truncates() {
+        let command = "x".repeat(200);
+        let description = describe("Bash", Some(&json!({ "command": command })), None);
+        assert_eq!(description.chars().count(), MAX_DESCRIPTION);
+        assert!(description.ends_with('…'));
+    }
+}
@@ -0,0 +1,455 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use crate::paths;
+
+/// Longest prompt or message kept in a summary, in characters
+const MAX_TEXT: usize = 500;
+/// Directory under the state dir with each session's read progress
+const CURSOR_DIR: &str = "transcripts";
+
+/// Token counts summed over the assistant messages of Claude Code transcripts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub input: u64,
+    pub output: u64,
+    pub cache_creation: u64,
+    pub cache_read: u64,
+}
+
+impl TokenUsage {
+    /// Everything the model read, cached or not
+    pub fn total_input(&self) -> u64 {
+        self.input + self.cache_creation + self.cache_read
+    }
+
+    /// Tokens used since an `earlier` snapshot of the same totals
+    pub fn since(&self, earlier: &TokenUsage) -> TokenUsage {
+        TokenUsage {
+            input: self.input.saturating_sub(earlier.input),
+            output: self.output.saturating_sub(earlier.output),
+            cache_creation: self.cache_creation.saturating_sub(earlier.cache_creation),
+            cache_read: self.cache_read.saturating_sub(earlier.cache_read),
+        }
+    }
+
+    fn add(&mut self, usage: &Usage) {
+        self.input += usage.input_tokens;
+        self.output += usage.output_tokens;
+        self.cache_creation += usage.cache_creation_input_tokens;
+        self.cache_read += usage.cache_read_input_tokens;
+    }
+}
+
+impl std::ops::AddAssign for TokenUsage {
+    fn add_assign(&mut self, other: TokenUsage) {
+        self.input += other.input;
+        self.output += other.output;
+        self.cache_creation += other.cache_creation;
+        self.cache_read += other.cache_read;
+    }
+}
+
+/// Usage of one model within a session
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModelUsage {
+    pub tokens: TokenUsage,
+    /// Estimated from list prices; `None` for models without a known price
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost_usd: Option<f64>,
+}
+
+/// What a session's transcript says so far
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TranscriptSummary {
+    pub tokens: TokenUsage,
+    /// Estimated cost of the models with a known price
+    pub cost_usd: f64,
+    #[serde(default)]
+    pub models: BTreeMap<String, ModelUsage>,
+    /// Prompts the user typed (tool results and meta messages don't count)
+    pub turns: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_prompt: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_message: Option<String>,
+}
+
+/// List prices in USD per million tokens
+struct Price {
+    input: f64,
+    output: f64,
+    cache_write: f64,
+    cache_read: f64,
+}
+
+const fn price(input: f64, output: f64) -> Price {
+    Price {
+        input,
+        output,
+        cache_write: input * 1.25,
+        cache_read: input * 0.1,
+    }
+}
+
+/// Matched against the model id in order, so older models with their own
+/// prices come before the family fallback
+const PRICES: &[(&str, Price)] = &[
+    ("3-opus", price(15.0, 75.0)),
+    ("opus-4-1", price(15.0, 75.0)),
+    ("opus-4-20", price(15.0, 75.0)),
+    ("opus", price(5.0, 25.0)),
+    ("sonnet", price(3.0, 15.0)),
+    ("3-haiku", price(0.25, 1.25)),
+    ("3-5-haiku", price(0.8, 4.0)),
+    ("haiku", price(1.0, 5.0)),
+];
+
+fn estimate_cost(model: &str, tokens: &TokenUsage) -> Option<f64> {
+    let (_, price) = PRICES.iter().find(|(pattern, _)| model.contains(pattern))?;
+    let cost = tokens.input as f64 * price.input
+        + tokens.output as f64 * price.output
+        + tokens.cache_creation as f64 * price.cache_write
+        + tokens.cache_read as f64 * price.cache_read;
+    Some(cost / 1_000_000.0)
+}
+
+#[derive(Deserialize)]
+struct Line {
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    message: Option<Message>,
+    #[serde(default, rename = "isMeta")]
+    is_meta: bool,
+    /// Lines from subagents
+    #[serde(default, rename = "isSidechain")]
+    is_sidechain: bool,
+    #[serde(default, rename = "isCompactSummary")]
+    is_compact_summary: bool,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    id: Option<String>,
+    model: Option<String>,
+    usage: Option<Usage>,
+    content: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Usage {
+    input_tokens: u64,
+    output_tokens: u64,
+    cache_creation_input_tokens: u64,
+    cache_read_input_tokens: u64,
+}
+
+/// Builds a summary from transcript lines
+#[derive(Default, Serialize, Deserialize)]
+struct Summarizer {
+    summary: TranscriptSummary,
+    /// A message that streams several content blocks is logged once per
+    /// block with the same id, so each id only counts once
+    seen: HashSet<String>,
+}
+
+/// How far a session's transcript has been summarized, so each hook only
+/// parses the lines appended since the last one
+#[derive(Default, Serialize, Deserialize)]
+struct Cursor {
+    /// Bytes read, always at the end of a line
+    offset: u64,
+    summarizer: Summarizer,
+}
+
+impl Cursor {
+    /// Add the complete lines appended to `path` since the last read.
+    /// Returns whether there were any.
+    fn read(&mut self, path: &Path) -> io::Result<bool> {
+        let mut file = File::open(path)?;
+        // A shorter file isn't the transcript the cursor was saved for
+        if file.metadata()?.len() < self.offset {
+            *self = Cursor::default();
+        }
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut tail = Vec::new();
+        file.read_to_end(&mut tail)?;
+
+        // A line still being written is left for the next read
+        let complete = tail.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+        self.summarizer.add_content(&String::from_utf8_lossy(&tail[..complete]));
+        self.offset += complete as u64;
+        Ok(complete > 0)
+    }
+}
+
+impl Summarizer {
+    fn add_content(&mut self, content: &str) {
+        for line in content.lines() {
+            if let Ok(line) = serde_json::from_str::<Line>(line) {
+                self.add_line(line);
+            }
+        }
+    }
+
+    fn add_line(&mut self, line: Line) {
+        let Some(message) = line.message else {
+            return;
+        };
+        match line.kind.as_deref() {
+            Some("user") => {
+                if line.is_meta || line.is_sidechain || line.is_compact_summary {
+                    return;
+                }
+                // Tool results come back as user messages without any text
+                if let Some(text) = message.content.as_ref().and_then(text_of) {
+                    self.summary.turns += 1;
+                    self.summary.last_prompt = Some(truncate(&text));
+                }
+            }
+            Some("assistant") => {
+                // Claude Code's own notices are logged as model "<synthetic>"
+                let model = message.model.filter(|m| !m.starts_with('<'));
+                if !line.is_sidechain
+                    && model.is_some()
+                    && let Some(text) = message.content.as_ref().and_then(text_of)
+                {
+                    self.summary.last_message = Some(truncate(&text));
+                }
+                if let Some(id) = message.id
+                    && !self.seen.insert(id)
+                {
+                    return;
+                }
+                let Some(usage) = &message.usage else {
+                    return;
+                };
+                self.summary.tokens.add(usage);
+                if let Some(model) = model {
+                    self.summary.models.entry(model).or_default().tokens.add(usage);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn finish(&mut self) -> TranscriptSummary {
+        let mut total = 0.0;
+        for (model, usage) in &mut self.summary.models {
+            usage.cost_usd = estimate_cost(model, &usage.tokens);
+            total += usage.cost_usd.unwrap_or(0.0);
+        }
+        self.summary.cost_usd = total;
+        self.summary.clone()
+    }
+}
+
+/// Text blocks of a message, or `None` if it has no text
+fn text_of(content: &serde_json::Value) -> Option<String> {
+    let text = match content {
+        serde_json::Value::String(s) => s.clone(),
+        serde_json::Value::Array(blocks) => blocks
+            .iter()
+            .filter(|b| b.get("type").and_then(|t| t.as_str()) == Some("text"))
+            .filter_map(|b| b.get("text")?.as_str())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => return None,
+    };
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+fn truncate(text: &str) -> String {
+    match text.char_indices().nth(MAX_TEXT) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+/// Summarize one session's transcript. Only the lines appended since the
+/// last call are parsed; the rest comes from the session's saved cursor.
+pub fn summarize(path: &Path) -> io::Result<TranscriptSummary> {
+    let cursor_path = path.file_stem().map(cursor_path);
+    let mut cursor: Cursor = cursor_path
+        .as_ref()
+        .and_then(|p| fs::read_to_string(p).ok())
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default();
+
+    let advanced = cursor.read(path)?;
+    let summary = cursor.summarizer.finish();
+
+    if let Some(cursor_path) = cursor_path
+        && advanced
+    {
+        fs::create_dir_all(paths::state_dir().join(CURSOR_DIR))?;
+        fs::write(cursor_path, serde_json::to_string(&cursor)?)?;
+    }
+    Ok(summary)
+}
+
+/// Drop the saved cursor of a session that ended
+pub fn forget(session_id: &str) {
+    let _ = fs::remove_file(cursor_path(session_id.as_ref()));
+}
+
+/// Transcripts are named after their session, so cursors are too
+fn cursor_path(session: &std::ffi::OsStr) -> PathBuf {
+    let mut name = session.to_os_string();
+    name.push(".json");
+    paths::state_dir().join(CURSOR_DIR).join(name)
+}
+
+/// Where Claude Code keeps the transcripts of sessions started in `cwd`
+fn project_dir(cwd: &Path) -> Option<PathBuf> {
+    let claude_dir = match std::env::var_os("CLAUDE_CONFIG_DIR").filter(|d| !d.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".claude"),
+    };
+    Some(claude_dir.join("projects").join(encode_project_path(cwd)))
+}
+
+/// Claude Code names project directories after the cwd with every
+/// non-alphanumeric character replaced by `-`
+fn encode_project_path(cwd: &Path) -> String {
+    cwd.to_string_lossy()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect()
+}
+
+/// Combined summary of all sessions started in `cwd`, or `None` if there
+/// are none
+pub fn summarize_dir(cwd: &Path) -> Option<TranscriptSummary> {
+    let entries = fs::read_dir(project_dir(cwd)?).ok()?;
+
+    let mut summarizer = Summarizer::default();
+    let mut found = false;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().is_none_or(|ext| ext != "jsonl") {
+            continue;
+        }
+        if let Ok(content) = fs::read_to_string(&path) {
+            found = true;
+            summarizer.add_content(&content);
+        }
+    }
+    found.then(|| summarizer.finish())
+}
+
+/// Compact token count, e.g. `950`, `12.3k`, `1.2M`
+pub fn format_tokens(n: u64) -> String {
+    match n {
+        0..1_000 => n.to_string(),
+        1_000..1_000_000 => format!("{:.1}k", n as f64 / 1_000.0),
+        _ => format!("{:.1}M", n as f64 / 1_000_000.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRANSCRIPT: &str = r#"{"type":"user","message":{"role":"user","content":"fix the bug"}}
+{"type":"assistant","message":{"id":"msg_1","model":"claude-sonnet-4-5","content":[{"type":"thinking","thinking":"hm"}],"usage":{"input_tokens":10,"output_tokens":5,"cache_read_input_tokens":100}}}
+{"type":"assistant","message":{"id":"msg_1","model":"claude-sonnet-4-5","content":[{"type":"tool_use","name":"Bash"}],"usage":{"input_tokens":10,"output_tokens":5,"cache_read_input_tokens":100}}}
+{"type":"user","message":{"role":"user","content":[{"type":"tool_result","content":"ok"}]}}
+{"type":"user","isMeta":true,"message":{"role":"user","content":[{"type":"text","text":"caveat"}]}}
+not json
+{"type":"assistant","message":{"id":"msg_2","model":"claude-opus-4-5","content":[{"type":"text","text":"Fixed it."}],"usage":{"input_tokens":3,"output_tokens":7,"cache_creation_input_tokens":20}}}
+{"type":"user","message":{"role":"user","content":[{"type":"text","text":"  thanks  "}]}}
+{"type":"assistant","message":{"id":"msg_3","model":"<synthetic>","content":[{"type":"text","text":"No response requested."}],"usage":{"input_tokens":0,"output_tokens":0}}}
+"#;
+
+    #[test]
+    fn test_encode_project_path() {
+        assert_eq!(
+            encode_project_path(Path::new("/home/me/src/app.fix_1")),
+            "-home-me-src-app-fix-1"
+        );
+    }
+
+    #[test]
+    fn test_summarize() {
+        let mut summarizer = Summarizer::default();
+        summarizer.add_content(TRANSCRIPT);
+        let summary = summarizer.finish();
+
+        assert_eq!(
+            summary.tokens,
+            TokenUsage {
+                input: 13,
+                output: 12,
+                cache_creation: 20,
+                cache_read: 100,
+            }
+        );
+        assert_eq!(summary.tokens.total_input(), 133);
+        assert_eq!(summary.turns, 2);
+        assert_eq!(summary.last_prompt.as_deref(), Some("thanks"));
+        assert_eq!(summary.last_message.as_deref(), Some("Fixed it."));
+
+        assert_eq!(summary.models.len(), 2);
+        let sonnet = &summary.models["claude-sonnet-4-5"];
+        assert_eq!(sonnet.tokens.output, 5);
+        // 10 * $3 + 5 * $15 + 100 * $0.30 per million
+        let expected = 135.0 / 1_000_000.0;
+        assert!((sonnet.cost_usd.unwrap() - expected).abs() < 1e-12);
+        assert!(summary.cost_usd > expected);
+    }
+
+    #[test]
+    fn test_cursor_reads_appended_lines() {
+        let path = std::env::temp_dir()
+            .join(format!("maestro-transcript-{}.jsonl", std::process::id()));
+        let head = &TRANSCRIPT[..TRANSCRIPT.find("msg_2").unwrap()];
+        fs::write(&path, head).unwrap();
+
+        let mut cursor = Cursor::default();
+        assert!(cursor.read(&path).unwrap());
+        assert_eq!(cursor.summarizer.finish().turns, 1);
+        // The half-written msg_2 line isn't read yet
+        assert_eq!(cursor.offset as usize, head.rfind('\n').unwrap() + 1);
+
+        fs::write(&path, TRANSCRIPT).unwrap();
+        assert!(cursor.read(&path).unwrap());
+        assert!(!cursor.read(&path).unwrap());
+        let mut full = Summarizer::default();
+        full.add_content(TRANSCRIPT);
+        assert_eq!(cursor.summarizer.finish(), full.finish());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_estimate_cost() {
+        let tokens = TokenUsage {
+            input: 1_000_000,
+            ..Default::default()
+        };
+        assert_eq!(estimate_cost("claude-opus-4-1-20250805", &tokens), Some(15.0));
+        assert_eq!(estimate_cost("claude-opus-4-5-20251101", &tokens), Some(5.0));
+        assert_eq!(estimate_cost("claude-3-5-haiku-20241022", &tokens), Some(0.8));
+        assert_eq!(estimate_cost("gpt-5", &tokens), None);
+    }
+
+    #[test]
+    fn test_truncate() {
+        let long = "x".repeat(MAX_TEXT + 10);
+        assert_eq!(truncate(&long).chars().count(), MAX_TEXT + 1);
+        assert_eq!(truncate("short"), "short");
+    }
+
+    #[test]
+    fn test_format_tokens() {
+        assert_eq!(format_tokens(950), "950");
+        assert_eq!(format_tokens(12_345), "12.3k");
+        assert_eq!(format_tokens(1_234_567), "1.2M");
+    }
+}
@@ -0,0 +1,488 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{self, Error, ErrorKind, Read, Write};
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use crate::agent::AgentKind;
+use crate::config::{Config, Webhook};
+use crate::instance::{unix_now, Instance, Status};
+use crate::worktree::git_context;
+use crate::WebhookCommands;
+
+/// Delay before the first retry; doubles with every attempt
+const BACKOFF: Duration = Duration::from_secs(1);
+/// Longest message excerpt sent with an event, in characters
+const MAX_MESSAGE: usize = 200;
+
+/// A change to the instance store
+pub enum Change {
+    Registered(Instance),
+    StatusChanged {
+        instance: Instance,
+        old: Status,
+        /// When the old status started
+        since: Option<u64>,
+    },
+    Unregistered(Instance),
+}
+
+impl Change {
+    fn instance(&self) -> &Instance {
+        match self {
+            Change::Registered(instance)
+            | Change::StatusChanged { instance, .. }
+            | Change::Unregistered(instance) => instance,
+        }
+    }
+}
+
+/// The JSON event sent for a change
+#[derive(Debug, Serialize)]
+pub struct Event {
+    /// `registered`, `status_changed` or `unregistered`
+    pub event: &'static str,
+    pub pane_id: u32,
+    /// Folder the instance runs in
+    pub instance: String,
+    pub agent: AgentKind,
+    pub cwd: Option<String>,
+    pub repo: Option<String>,
+    pub branch: Option<String>,
+    pub old_status: Option<String>,
+    /// `running`, `waiting`, `permission` or `exited`
+    pub new_status: String,
+    /// Seconds spent in the old status
+    pub duration: Option<u64>,
+    /// Seconds since the instance registered
+    pub lifetime: Option<u64>,
+    /// Excerpt of the agent's last message
+    pub message: Option<String>,
+    pub timestamp: u64,
+}
+
+impl Event {
+    fn new(change: &Change, now: u64) -> Self {
+        let instance = change.instance();
+        let (event, old_status, new_status, since) = match change {
+            Change::Registered(i) => ("registered", None, i.status.to_string(), None),
+            Change::StatusChanged { instance, old, since } => (
+                "status_changed",
+                Some(old.to_string()),
+                instance.status.to_string(),
+                *since,
+            ),
+            Change::Unregistered(i) => (
+                "unregistered",
+                Some(i.status.to_string()),
+                "exited".to_string(),
+                i.status_since,
+            ),
+        };
+        Event {
+            event,
+            pane_id: instance.pane_id,
+            instance: instance.folder.clone(),
+            agent: instance.agent,
+            cwd: instance.cwd.clone(),
+            repo: instance.repo.clone(),
+            branch: instance.branch.clone(),
+            old_status,
+            new_status,
+            duration: since.map(|s| now.saturating_sub(s)),
+            lifetime: instance.registered_at.map(|r| now.saturating_sub(r)),
+            message: instance
+                .transcript
+                .as_ref()
+                .and_then(|t| t.last_message.as_deref())
+                .map(excerpt),
+            timestamp: now,
+        }
+    }
+
+    /// Values for a payload template, as strings
+    fn placeholders(&self) -> Vec<(&'static str, String)> {
+        let opt = |value: &Option<String>| value.clone().unwrap_or_default();
+        let num = |value: Option<u64>| value.map(|n| n.to_string()).unwrap_or_default();
+        vec![
+            ("event", self.event.to_string()),
+            ("pane_id", self.pane_id.to_string()),
+            ("instance", self.instance.clone()),
+            ("agent", self.agent.to_string()),
+            ("cwd", opt(&self.cwd)),
+            ("repo", opt(&self.repo)),
+            ("branch", opt(&self.branch)),
+            ("old_status", opt(&self.old_status)),
+            ("new_status", self.new_status.clone()),
+            ("duration", num(self.duration)),
+            ("lifetime", num(self.lifetime)),
+            ("message", opt(&self.message)),
+            ("timestamp", self.timestamp.to_string()),
+        ]
+    }
+}
+
+fn excerpt(message: &str) -> String {
+    match message.char_indices().nth(MAX_MESSAGE) {
+        Some((end, _)) => format!("{}…", &message[..end]),
+        None => message.to_string(),
+    }
+}
+
+/// Whether the webhook wants an event
+fn wants(webhook: &Webhook, event: &Event) -> bool {
+    if !webhook.statuses.is_empty() && !webhook.statuses.contains(&event.new_status) {
+        return false;
+    }
+    event.event != "status_changed" || event.duration.unwrap_or(0) >= webhook.min_duration
+}
+
+/// The request body for an event
+fn payload(webhook: &Webhook, event: &Event) -> io::Result<String> {
+    match &webhook.template {
+        Some(template) => Ok(render(template, &event.placeholders())),
+        None => Ok(serde_json::to_string(event)?),
+    }
+}
+
+/// Substitute `{name}` placeholders, escaping values for use inside JSON
+/// strings. Anything else in braces (like the JSON itself) is left alone.
+fn render(template: &str, vars: &[(&str, String)]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let value = after
+            .find('}')
+            .and_then(|end| vars.iter().find(|(name, _)| *name == &after[..end]).map(|v| (end, v)));
+        match value {
+            Some((end, (_, value))) => {
+                let quoted = serde_json::to_string(value).unwrap_or_default();
+                out.push_str(&quoted[1..quoted.len() - 1]);
+                rest = &after[end + 1..];
+            }
+            None => {
+                out.push('{');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Everything a detached `maestro webhook deliver` needs to send events
+#[derive(Debug, Serialize, Deserialize)]
+struct Delivery {
+    url: String,
+    headers: BTreeMap<String, String>,
+    retries: u32,
+    timeout: u64,
+    payloads: Vec<String>,
+}
+
+/// Send the webhook events for some store changes, each with the settings of
+/// its own instance's repository. Delivery (and its retries) happens in a
+/// detached process so hooks never wait on the network.
+pub fn emit(changes: Vec<Change>) {
+    for (cwd, changes) in by_cwd(changes) {
+        if let Ok(config) = Config::load(Path::new(&cwd)) {
+            emit_with(config.webhook, &changes);
+        }
+    }
+}
+
+/// Group changes by the directory their config is loaded from
+fn by_cwd(changes: Vec<Change>) -> BTreeMap<String, Vec<Change>> {
+    let mut groups: BTreeMap<String, Vec<Change>> = BTreeMap::new();
+    for change in changes {
+        let cwd = change.instance().cwd.clone().unwrap_or_default();
+        groups.entry(cwd).or_default().push(change);
+    }
+    groups
+}
+
+fn emit_with(webhook: Webhook, changes: &[Change]) {
+    let Some(url) = webhook.url.clone() else {
+        return;
+    };
+
+    let now = unix_now();
+    let payloads: Vec<String> = changes
+        .iter()
+        .map(|change| Event::new(change, now))
+        .filter(|event| wants(&webhook, event))
+        .filter_map(|event| payload(&webhook, &event).ok())
+        .collect();
+    if payloads.is_empty() {
+        return;
+    }
+
+    let delivery = Delivery {
+        url,
+        headers: webhook.headers,
+        retries: webhook.retries,
+        timeout: webhook.timeout,
+        payloads,
+    };
+    if let Err(e) = spawn_delivery(&delivery) {
+        eprintln!("Warning: Could not send webhook: {}", e);
+    }
+}
+
+fn spawn_delivery(delivery: &Delivery) -> io::Result<()> {
+    let mut child = Command::new(std::env::current_exe()?)
+        .args(["webhook", "deliver"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(serde_json::to_string(delivery)?.as_bytes())?;
+    }
+    Ok(())
+}
+
+pub fn run(command: WebhookCommands) -> io::Result<()> {
+    match command {
+        WebhookCommands::Test => cmd_test(),
+        WebhookCommands::Deliver => cmd_deliver(),
+    }
+}
+
+/// Send a sample event for the current directory and report the result
+fn cmd_test() -> io::Result<()> {
+    let cwd = std::env::current_dir()?;
+    let webhook = Config::load(&cwd)?.webhook;
+    let url = webhook.url.clone().ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            "No webhook configured: set [webhook] url in the config",
+        )
+    })?;
+
+    let now = unix_now();
+    let (repo, branch) = git_context(&cwd);
+    let event = Event {
+        event: "status_changed",
+        pane_id: 0,
+        instance: cwd
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        agent: AgentKind::default(),
+        cwd: Some(cwd.to_string_lossy().to_string()),
+        repo,
+        branch,
+        old_status: Some(Status::Running.to_string()),
+        new_status: Status::Waiting.to_string(),
+        duration: Some(42),
+        lifetime: Some(600),
+        message: Some("Test event from maestro".to_string()),
+        timestamp: now,
+    };
+    let delivery = Delivery {
+        url,
+        headers: webhook.headers.clone(),
+        retries: webhook.retries,
+        timeout: webhook.timeout,
+        payloads: vec![payload(&webhook, &event)?],
+    };
+    post(&delivery, &delivery.payloads[0], BACKOFF)?;
+    println!("Sent test event to {}", delivery.url);
+    Ok(())
+}
+
+fn cmd_deliver() -> io::Result<()> {
+    let mut input = String::new();
+    io::stdin().read_to_string(&mut input)?;
+    let delivery: Delivery = serde_json::from_str(&input)?;
+    for payload in &delivery.payloads {
+        if let Err(e) = post(&delivery, payload, BACKOFF) {
+            eprintln!("Warning: {}", e);
+        }
+    }
+    Ok(())
+}
+
+/// POST one payload, retrying server errors and network failures
+fn post(delivery: &Delivery, payload: &str, backoff: Duration) -> io::Result<()> {
+    let agent = ureq::AgentBuilder::new()
+        .timeout(Duration::from_secs(delivery.timeout))
+        .build();
+
+    let mut delay = backoff;
+    let mut attempt = 0;
+    loop {
+        let mut request = agent
+            .post(&delivery.url)
+            .set("Content-Type", "application/json");
+        for (name, value) in &delivery.headers {
+            request = request.set(name, value);
+        }
+
+        let error = match request.send_string(payload) {
+            Ok(_) => return Ok(()),
+            Err(ureq::Error::Status(code, _)) if !retryable(code) => {
+                return Err(Error::new(
+                    ErrorKind::Other,
+                    format!("Webhook rejected the event (HTTP {})", code),
+                ));
+            }
+            Err(e) => e,
+        };
+
+        if attempt >= delivery.retries {
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!("Webhook failed after {} attempt(s): {}", attempt + 1, error),
+            ));
+        }
+        attempt += 1;
+        std::thread::sleep(delay);
+        delay *= 2;
+    }
+}
+
+fn retryable(code: u16) -> bool {
+    code == 408 || code == 429 || code >= 500
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::thread::JoinHandle;
+
+    /// Answer one request per status code, returning the request bodies
+    fn stub_server(statuses: Vec<u16>) -> (String, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let handle = std::thread::spawn(move || {
+            let mut bodies = Vec::new();
+            for status in statuses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':')
+                        && name.eq_ignore_ascii_case("content-length")
+                    {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                bodies.push(String::from_utf8(body).unwrap());
+
+                let response = format!("HTTP/1.1 {} Stub\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status);
+                reader.get_mut().write_all(response.as_bytes()).unwrap();
+            }
+            bodies
+        });
+        (url, handle)
+    }
+
+    fn delivery(url: String, retries: u32) -> Delivery {
+        Delivery {
+            url,
+            headers: BTreeMap::new(),
+            retries,
+            timeout: 5,
+            payloads: Vec::new(),
+        }
+    }
+
+    fn event(event: &'static str, new_status: &str, duration: u64) -> Event {
+        Event {
+            event,
+            pane_id: 3,
+            instance: "app.fix-1".to_string(),
+            agent: AgentKind::Claude,
+            cwd: None,
+            repo: Some("app".to_string()),
+            branch: Some("fix-1".to_string()),
+            old_status: Some("running".to_string()),
+            new_status: new_status.to_string(),
+            duration: Some(duration),
+            lifetime: None,
+            message: Some("Done: \"all\" tests pass".to_string()),
+            timestamp: 0,
+        }
+    }
+
+    #[test]
+    fn test_changes_are_grouped_by_cwd() {
+        let unregistered = |pane_id, cwd: &str| {
+            Change::Unregistered(Instance {
+                pane_id,
+                cwd: Some(cwd.to_string()),
+                ..Instance::for_test("app", None, None, Status::Waiting)
+            })
+        };
+        let groups = by_cwd(vec![
+            unregistered(1, "/src/app"),
+            unregistered(2, "/src/api"),
+            unregistered(3, "/src/app"),
+        ]);
+        let panes: Vec<(&str, Vec<u32>)> = groups
+            .iter()
+            .map(|(cwd, changes)| (cwd.as_str(), changes.iter().map(|c| c.instance().pane_id).collect()))
+            .collect();
+        assert_eq!(panes, [("/src/api", vec![2]), ("/src/app", vec![1, 3])]);
+    }
+
+    #[test]
+    fn test_post_retries_server_errors() {
+        let (url, server) = stub_server(vec![500, 503, 200]);
+        post(&delivery(url, 3), r#"{"ok":true}"#, Duration::ZERO).unwrap();
+        assert_eq!(server.join().unwrap(), vec![r#"{"ok":true}"#; 3]);
+    }
+
+    #[test]
+    fn test_post_gives_up() {
+        let (url, server) = stub_server(vec![500, 500]);
+        let err = post(&delivery(url, 1), "{}", Duration::ZERO).unwrap_err();
+        assert!(err.to_string().contains("after 2 attempt(s)"), "{}", err);
+        server.join().unwrap();
+
+        // Client errors won't get better by retrying
+        let (url, server) = stub_server(vec![404]);
+        let err = post(&delivery(url, 3), "{}", Duration::ZERO).unwrap_err();
+        assert!(err.to_string().contains("HTTP 404"), "{}", err);
+        assert_eq!(server.join().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_render_template() {
+        let template = r#"{"text": "{repo}/{branch}: {instance} is {new_status} after {duration}s. {message} {unknown}"}"#;
+        assert_eq!(
+            render(template, &event("status_changed", "waiting", 95).placeholders()),
+            r#"{"text": "app/fix-1: app.fix-1 is waiting after 95s. Done: \"all\" tests pass {unknown}"}"#
+        );
+    }
+
+    #[test]
+    fn test_wants() {
+        let webhook = Webhook {
+            statuses: vec!["waiting".to_string(), "exited".to_string()],
+            min_duration: 60,
+            ..Default::default()
+        };
+        assert!(wants(&webhook, &event("status_changed", "waiting", 60)));
+        assert!(!wants(&webhook, &event("status_changed", "waiting", 59)));
+        assert!(!wants(&webhook, &event("status_changed", "running", 600)));
+        assert!(wants(&webhook, &event("unregistered", "exited", 1)));
+        assert!(wants(&Webhook::default(), &event("registered", "running", 0)));
+    }
+}
@@ -1,9 +1,24 @@
+use crate::agent::{self, AgentOptions};
+use crate::beads;
 use crate::config::Config;
-use crate::layout::{generate_layout, get_editor_command, write_temp_layout};
+use crate::hooks::{self, HookContext};
+use crate::instance::{unix_now, Instance, InstanceStore};
+use crate::layout::{
+    combine_layouts, generate_layout, get_editor_command, install_done_marker, load_template,
+    parse_layout, tab_name, write_temp_layout,
+};
+use crate::ports::{PortBlock, PortStore};
+use crate::transcript::{self, TranscriptSummary};
+use crate::webhook::{self, Change};
+use crate::{limits, seed, zellij};
 use crate::WtCommands;
+use serde::Serialize;
 use std::io::{self, Error, ErrorKind};
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+const DEFAULT_PATH_TEMPLATE: &str = "../{repo}.{branch}";
 
 pub struct Worktree {
     pub path: PathBuf,
@@ -13,58 +28,383 @@ pub struct Worktree {
 
 pub fn run(cmd: WtCommands) -> io::Result<()> {
     match cmd {
-        WtCommands::List => cmd_list(),
-        WtCommands::Switch { branch } => cmd_switch(&branch),
-        WtCommands::Remove => cmd_remove(),
+        WtCommands::List { json } => cmd_list(json),
+        WtCommands::Switch {
+            branch,
+            issue,
+            print_layout,
+            wait,
+            agent,
+            agent_args,
+        } => {
+            let agent = AgentOptions {
+                kind: agent,
+                args: agent_args,
+                ..Default::default()
+            };
+            match issue {
+                Some(issue) => cmd_switch_issue(&issue, branch, print_layout, wait, agent),
+                None => cmd_switch(&branch.expect("required by clap"), print_layout, wait, &agent),
+            }
+        }
+        WtCommands::Remove {
+            branches,
+            force,
+            keep_branch,
+            yes,
+        } => cmd_remove(
+            &branches,
+            &RemoveOptions {
+                force,
+                keep_branch,
+                yes,
+            },
+        ),
         WtCommands::Merge => cmd_merge(),
+        WtCommands::Compare { branches, no_check } => cmd_compare(&branches, !no_check),
+        WtCommands::Prune { days, yes } => cmd_prune(days, yes),
     }
 }
 
-fn cmd_list() -> io::Result<()> {
+/// Detailed view of a worktree, as shown by `wt list`
+#[derive(Debug, Serialize)]
+pub struct WorktreeInfo {
+    pub branch: Option<String>,
+    pub path: PathBuf,
+    pub is_current: bool,
+    pub is_bare: bool,
+    pub dirty: bool,
+    /// Commits on this worktree's HEAD that are not on the default branch
+    pub ahead: Option<u32>,
+    /// Commits on the default branch that are not on this worktree's HEAD
+    pub behind: Option<u32>,
+    pub last_commit: Option<CommitInfo>,
+    /// Changes (including uncommitted ones) since forking from the default branch
+    pub diff: Option<DiffStat>,
+    /// Registered instances running inside this worktree
+    pub instances: Vec<Instance>,
+    /// Ports reserved for this worktree's processes
+    pub ports: Option<PortBlock>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CommitInfo {
+    pub subject: String,
+    /// Unix timestamp of the commit
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct DiffStat {
+    pub files: u32,
+    pub insertions: u32,
+    pub deletions: u32,
+}
+
+fn cmd_list(json: bool) -> io::Result<()> {
     let worktrees = list_worktrees()?;
     let current_dir = std::env::current_dir()?;
+    let default_branch = get_default_branch().ok();
+    let instances = InstanceStore::new().load();
+    let ports = PortStore::new().load();
+
+    let mut infos: Vec<WorktreeInfo> = worktrees
+        .iter()
+        .map(|wt| worktree_info(wt, &current_dir, default_branch.as_deref()))
+        .collect();
+
+    for info in &mut infos {
+        info.ports = ports.get(&*info.path.to_string_lossy()).copied();
+    }
+
+    for inst in instances {
+        let Some(cwd) = inst.cwd.as_deref().map(Path::new) else {
+            continue;
+        };
+        // Nested worktrees: attribute the instance to the deepest match
+        if let Some(info) = infos
+            .iter_mut()
+            .filter(|info| cwd.starts_with(&info.path))
+            .max_by_key(|info| info.path.components().count())
+        {
+            info.instances.push(inst);
+        }
+    }
 
-    if worktrees.is_empty() {
+    if json {
+        let output = serde_json::json!({
+            "version": crate::VERSION,
+            "build": crate::BUILD_PROFILE,
+            "default_branch": default_branch,
+            "worktrees": infos,
+        });
+        println!("{}", output);
+        return Ok(());
+    }
+
+    if infos.is_empty() {
         println!("No worktrees found");
         return Ok(());
     }
 
-    for wt in worktrees {
-        let is_current = wt.path == current_dir;
-        let marker = if is_current { "*" } else { " " };
-        let dirty = if !wt.is_bare && is_dirty(&wt.path) {
-            " [dirty]"
-        } else {
-            ""
-        };
-        let branch = wt.branch.as_deref().unwrap_or("(bare)");
-        println!("{} {} {}{}", marker, branch, wt.path.display(), dirty);
+    let now = unix_now();
+    let branch_width = infos
+        .iter()
+        .map(|info| info.branch.as_deref().unwrap_or("(bare)").chars().count())
+        .max()
+        .unwrap_or(0);
+    let path_width = infos
+        .iter()
+        .map(|info| info.path.display().to_string().chars().count())
+        .max()
+        .unwrap_or(0);
+
+    for info in &infos {
+        let marker = if info.is_current { "*" } else { " " };
+        let branch = info.branch.as_deref().unwrap_or("(bare)");
+
+        let mut columns = Vec::new();
+        if let (Some(ahead), Some(behind)) = (info.ahead, info.behind) {
+            columns.push(format!("↑{} ↓{}", ahead, behind));
+        }
+        if let Some(diff) = info.diff.as_ref().filter(|d| d.files > 0) {
+            columns.push(format!("+{} -{}", diff.insertions, diff.deletions));
+        }
+        if let Some(commit) = &info.last_commit {
+            columns.push(format!(
+                "{} {}",
+                format_age(now.saturating_sub(commit.timestamp)),
+                commit.subject
+            ));
+        }
+        if let Some(ports) = info.ports {
+            columns.push(format!(":{}", ports));
+        }
+        for inst in &info.instances {
+            columns.push(format!("{} {} (pane {})", inst.status.icon(), inst.status, inst.pane_id));
+        }
+        if info.dirty {
+            columns.push("[dirty]".to_string());
+        }
+
+        println!(
+            "{} {:<branch_width$}  {:<path_width$}  {}",
+            marker,
+            branch,
+            info.path.display().to_string(),
+            columns.join("  "),
+        );
     }
 
     Ok(())
 }
 
-fn cmd_switch(branch: &str) -> io::Result<()> {
-    let worktrees = list_worktrees()?;
+fn worktree_info(wt: &Worktree, current_dir: &Path, default_branch: Option<&str>) -> WorktreeInfo {
+    let mut info = WorktreeInfo {
+        branch: wt.branch.clone(),
+        path: wt.path.clone(),
+        is_current: wt.path == current_dir,
+        is_bare: wt.is_bare,
+        dirty: false,
+        ahead: None,
+        behind: None,
+        last_commit: None,
+        diff: None,
+        instances: Vec::new(),
+        ports: None,
+    };
 
-    // Check if worktree already exists for this branch
-    if let Some(wt) = worktrees.iter().find(|w| w.branch.as_deref() == Some(branch)) {
-        launch_zellij_layout(&wt.path, false)?;
+    if wt.is_bare || !wt.path.exists() {
+        return info;
+    }
+
+    info.dirty = is_dirty(&wt.path);
+    info.last_commit = git_output(&wt.path, &["log", "-1", "--format=%ct%x09%s"])
+        .and_then(|out| parse_commit_line(&out));
+
+    if let Some(default_branch) = default_branch {
+        let range = format!("{}...HEAD", default_branch);
+        if let Some((behind, ahead)) =
+            git_output(&wt.path, &["rev-list", "--left-right", "--count", &range])
+                .and_then(|out| parse_left_right(&out))
+        {
+            info.behind = Some(behind);
+            info.ahead = Some(ahead);
+        }
+
+        info.diff = git_output(&wt.path, &["merge-base", default_branch, "HEAD"])
+            .and_then(|base| git_output(&wt.path, &["diff", "--shortstat", &base]))
+            .map(|out| parse_shortstat(&out));
+    }
+
+    info
+}
+
+fn cmd_switch(branch: &str, print_layout: bool, wait: bool, agent: &AgentOptions) -> io::Result<()> {
+    reserve_agents(&[branch.to_string()], wait)?;
+    let (worktree_path, is_new) = ensure_worktree(branch, &reporter(print_layout))?;
+    let layout = worktree_layout(&worktree_path, branch, is_new, agent)?;
+    launch_layout(&layout, &worktree_path, print_layout)
+}
+
+/// Open a worktree for a beads issue, on a branch named after it unless
+/// `branch` is given, with the issue as the agent's initial prompt
+fn cmd_switch_issue(
+    issue_id: &str,
+    branch: Option<String>,
+    print_layout: bool,
+    wait: bool,
+    mut agent: AgentOptions,
+) -> io::Result<()> {
+    let report = reporter(print_layout);
+    let issue = beads::find_issue(&std::env::current_dir()?, issue_id)?;
+    if issue.status.as_deref() == Some("closed") {
+        eprintln!("Warning: Issue {} is closed", issue.id);
+    }
+    let branch = branch.unwrap_or_else(|| issue.branch_name());
+    reserve_agents(std::slice::from_ref(&branch), wait)?;
+
+    let (worktree_path, is_new) = ensure_worktree(&branch, &report)?;
+    if let Err(e) = beads::link_branch(&worktree_path, &branch, &issue.id) {
+        eprintln!("Warning: Could not link branch to issue: {}", e);
+    }
+    report(format!("Working on {}: {}", issue.id, issue.title));
+
+    agent.prompt = Some(issue.prompt());
+    let layout = worktree_layout(&worktree_path, &branch, is_new, &agent)?;
+    launch_layout(&layout, &worktree_path, print_layout)
+}
+
+/// Open a worktree of the repository at `repo_path` as a tab in the current
+/// Zellij session, with an agent started on `prompt`
+pub fn spawn_agent(repo_path: &Path, branch: &str, prompt: &str) -> io::Result<()> {
+    std::env::set_current_dir(repo_path)?;
+    reserve_agents(&[branch.to_string()], false)?;
+    let (worktree_path, is_new) = ensure_worktree(branch, &reporter(false))?;
+    let agent = AgentOptions {
+        prompt: Some(prompt.to_string()),
+        ..Default::default()
+    };
+    let layout = worktree_layout(&worktree_path, branch, is_new, &agent)?;
+    launch_layout(&layout, &worktree_path, false)
+}
+
+/// Create worktrees `<name>-1..<name>-N` and open them as tabs, each running
+/// an agent registered under batch `name`
+pub fn cmd_fanout(
+    name: &str,
+    count: usize,
+    agent: &AgentOptions,
+    print_layout: bool,
+    wait: bool,
+) -> io::Result<()> {
+    if count == 0 {
+        return Err(Error::new(ErrorKind::InvalidInput, "Need at least one worktree"));
+    }
+    let branches: Vec<String> = (1..=count).map(|i| format!("{}-{}", name, i)).collect();
+
+    // Worktrees already open in a tab, or running an agent elsewhere, are left
+    // alone so they don't get a second agent
+    let opened: Vec<&String> = if print_layout {
+        Vec::new()
+    } else {
+        let tabs = zellij::open_tab_names(&zellij::session_name(&get_repo_name()?))?;
+        let worktrees = list_worktrees()?;
+        let instances = InstanceStore::new().load();
+        branches
+            .iter()
+            .filter(|branch| {
+                has_agent(branch, &worktrees, &instances)
+                    || worktrees
+                        .iter()
+                        .filter(|wt| wt.branch.as_ref() == Some(*branch))
+                        .any(|wt| tabs.contains(&tab_name(&wt.path)))
+            })
+            .collect()
+    };
+    let to_open: Vec<String> = branches.iter().filter(|b| !opened.contains(b)).cloned().collect();
+    reserve_agents(&to_open, wait)?;
+
+    let report = reporter(print_layout);
+    let mut layouts = Vec::new();
+    let mut first_path = None;
+    for (i, branch) in branches.iter().enumerate() {
+        let (worktree_path, is_new) = ensure_worktree(branch, &report)?;
+        // With every tab open, the first one's layout just gets it focused
+        if to_open.contains(branch) || (i == 0 && to_open.is_empty()) {
+            layouts.push(worktree_layout(&worktree_path, branch, is_new, agent)?);
+            first_path.get_or_insert(worktree_path);
+        }
+    }
+
+    let layout = combine_layouts(&layouts)?;
+    let first_path = first_path.expect("count is at least one");
+    launch_layout(&layout, &first_path, print_layout)
+}
+
+/// Whether an agent is running in the branch's worktree
+fn has_agent(branch: &str, worktrees: &[Worktree], instances: &[Instance]) -> bool {
+    worktrees
+        .iter()
+        .filter(|wt| wt.branch.as_deref() == Some(branch))
+        .any(|wt| {
+            instances
+                .iter()
+                .any(|i| i.cwd.as_deref().is_some_and(|cwd| Path::new(cwd).starts_with(&wt.path)))
+        })
+}
+
+/// Reserve `[limits]` slots for the agents opening `branches` would start,
+/// waiting for a free slot if `wait`. Worktrees that already have an agent only get
+/// their tab focused, so they don't count.
+fn reserve_agents(branches: &[String], wait: bool) -> io::Result<()> {
+    let config = Config::load(&std::env::current_dir()?)?;
+    if config.limits.max_agents.is_none() && config.limits.max_processes.is_none() {
+        return Ok(());
+    }
+    let worktrees = list_worktrees()?;
+    let instances = InstanceStore::new().load();
+    let new: Vec<String> = branches
+        .iter()
+        .filter(|b| !has_agent(b, &worktrees, &instances))
+        .cloned()
+        .collect();
+    if new.is_empty() {
         return Ok(());
     }
+    let repo = get_repo_name()?;
+    limits::acquire(&config.limits, &repo, &new, limits::process_count(&config), wait)
+}
 
-    // Create new worktree
-    let repo_root = get_repo_root()?;
-    let repo_name = repo_root
-        .file_name()
-        .and_then(|n| n.to_str())
-        .ok_or_else(|| Error::new(ErrorKind::Other, "Cannot determine repo name"))?;
+/// Progress messages go to stderr when stdout is reserved for the layout
+fn reporter(print_layout: bool) -> impl Fn(String) {
+    move |msg| {
+        if print_layout {
+            eprintln!("{}", msg);
+        } else {
+            println!("{}", msg);
+        }
+    }
+}
 
-    let parent_dir = repo_root
-        .parent()
-        .ok_or_else(|| Error::new(ErrorKind::Other, "Cannot determine parent directory"))?;
+/// Find the branch's worktree, creating it (with seeded files and the
+/// post_create hook) if there is none. Returns its path and whether it's new.
+fn ensure_worktree(branch: &str, report: &dyn Fn(String)) -> io::Result<(PathBuf, bool)> {
+    let worktrees = list_worktrees()?;
+
+    // Check if worktree already exists for this branch
+    if let Some(wt) = worktrees.iter().find(|w| w.branch.as_deref() == Some(branch)) {
+        return Ok((wt.path.clone(), false));
+    }
 
-    let worktree_path = parent_dir.join(format!("{}.{}", repo_name, branch));
+    // Create new worktree next to the main one (or wherever the config says)
+    let config = Config::load(&std::env::current_dir()?)?;
+    let main_dir = match worktrees.first() {
+        Some(main_wt) => main_wt.path.clone(),
+        None => get_repo_root()?,
+    };
+    let template = config.worktree.path_template.as_deref().unwrap_or(DEFAULT_PATH_TEMPLATE);
+    let worktree_path = expand_path_template(template, &main_dir, &get_repo_name()?, branch);
     let default_branch = get_default_branch()?;
 
     // Create worktree with new branch
@@ -79,121 +419,368 @@ fn cmd_switch(branch: &str) -> io::Result<()> {
         return Err(Error::new(ErrorKind::Other, stderr.to_string()));
     }
 
-    println!("Created worktree at {}", worktree_path.display());
-    launch_zellij_layout(&worktree_path, true)?;
-    Ok(())
-}
+    report(format!("Created worktree at {}", worktree_path.display()));
 
-/// Launch Zellij with a layout for the worktree
-fn launch_zellij_layout(worktree_path: &Path, is_new: bool) -> io::Result<()> {
-    let config = Config::load(worktree_path);
-    let editor = get_editor_command();
+    let config = Config::load(&worktree_path)?;
 
-    // Determine which hooks to run
-    let (install_cmd, start_cmd) = match &config {
-        Some(cfg) => {
-            let install = if is_new || !Config::install_completed(worktree_path) {
-                cfg.hooks.install.as_deref()
-            } else {
-                None
-            };
-            let start = cfg.hooks.start.as_deref();
-            (install, start)
+    // Bring over untracked files (e.g. `.env`) and dependency caches before
+    // the install hook runs
+    if let Some(main_wt) = worktrees.first() {
+        match seed::copy_matching(&main_wt.path, &worktree_path, &config.copy.patterns) {
+            Ok(copied) => copied
+                .iter()
+                .for_each(|p| report(format!("Copied {}", p.display()))),
+            Err(e) => eprintln!("Warning: Could not copy files: {}", e),
         }
-        None => (None, None),
-    };
+        match seed::symlink_matching(&main_wt.path, &worktree_path, &config.symlink.patterns) {
+            Ok(linked) => linked
+                .iter()
+                .for_each(|p| report(format!("Linked {}", p.display()))),
+            Err(e) => eprintln!("Warning: Could not link files: {}", e),
+        }
+        match seed::seed_cache(
+            &main_wt.path,
+            &worktree_path,
+            &config.cache.dirs,
+            config.cache.fallback,
+        ) {
+            Ok(seeded) => seeded
+                .iter()
+                .for_each(|(p, stats)| report(format!("Seeded {} ({})", p.display(), stats))),
+            Err(e) => eprintln!("Warning: Could not seed cache directories: {}", e),
+        }
+    }
+
+    // Reserve ports up front so post_create already sees them
+    allocate_ports(&worktree_path, &config);
+    let ctx = hook_context(&worktree_path, Some(branch));
+    hooks::run_post("post_create", config.hooks.post_create.as_deref(), &ctx, &worktree_path);
+
+    Ok((worktree_path, true))
+}
+
+/// Run the pre_open hook and render the worktree's layout
+fn worktree_layout(
+    worktree_path: &Path,
+    branch: &str,
+    is_new: bool,
+    agent: &AgentOptions,
+) -> io::Result<String> {
+    let config = Config::load(worktree_path)?;
+    let ctx = hook_context(worktree_path, Some(branch));
+    hooks::run("pre_open", config.hooks.pre_open.as_deref(), &ctx, worktree_path)?;
+
+    build_worktree_layout(worktree_path, &config, agent, is_new)
+}
+
+/// Launch Zellij with a layout, or print the layout to stdout
+fn launch_layout(layout: &str, worktree_path: &Path, print_layout: bool) -> io::Result<()> {
+    if print_layout {
+        print!("{}", layout);
+        return Ok(());
+    }
+
+    let layout_path = write_temp_layout(layout)?;
+    let session = zellij::session_name(&get_repo_name()?);
+    zellij::open_layout(&layout_path, &tab_name(worktree_path), worktree_path, &session)
+}
 
-    // Generate layout
-    let layout = generate_layout(worktree_path, &editor, install_cmd, start_cmd);
-    let layout_path = write_temp_layout(&layout)?;
+/// Generate the Zellij layout for the worktree
+fn build_worktree_layout(
+    worktree_path: &Path,
+    config: &Config,
+    agent: &AgentOptions,
+    is_new: bool,
+) -> io::Result<String> {
+    let editor = get_editor_command(config.editor.as_deref());
+    let agent = agent::pane_command(&config.agent, agent)?;
+
+    // Determine which hooks and processes to run
+    let install_cmd = if is_new || !Config::install_completed(worktree_path) {
+        config.hooks.install.as_deref()
+    } else {
+        None
+    };
+    let processes = config.processes();
+
+    let env = allocate_ports(worktree_path, config);
+
+    // Render the layout and check it before Zellij sees it
+    let template = load_template(config.layout.template.as_deref(), worktree_path)?;
+    let layout = generate_layout(
+        &template,
+        worktree_path,
+        &editor,
+        &agent,
+        install_cmd,
+        &processes,
+        &env,
+    );
+    parse_layout(&layout)?;
+
+    // Templates without a process stack never run the install hook
+    let install_cmd = install_cmd.filter(|_| template.contains("{processes}"));
 
     // Mark install as completed if we're running it
     if install_cmd.is_some() {
         // We mark it before running so subsequent opens don't re-run install
         // even if the current session is interrupted
         let _ = Config::mark_install_completed(worktree_path);
+        // Processes wait for the install pane to create this again
+        let marker = install_done_marker(worktree_path);
+        if let Some(dir) = marker.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let _ = std::fs::remove_file(marker);
     }
 
-    // Launch Zellij with the layout
-    exec_zellij(&layout_path, worktree_path)
+    Ok(layout)
 }
 
-fn exec_zellij(layout_path: &Path, cwd: &Path) -> io::Result<()> {
-    use std::os::unix::process::CommandExt;
+/// Expand a worktree path template (`{repo}`, `{branch}`), resolving relative
+/// paths against the main worktree
+fn expand_path_template(template: &str, main_dir: &Path, repo: &str, branch: &str) -> PathBuf {
+    let expanded = template.replace("{repo}", repo).replace("{branch}", branch);
+    let expanded = match expanded.strip_prefix("~/") {
+        Some(rest) => std::env::var_os("HOME")
+            .map(|home| PathBuf::from(home).join(rest))
+            .unwrap_or_else(|| PathBuf::from(&expanded)),
+        None => PathBuf::from(&expanded),
+    };
 
-    std::env::set_current_dir(cwd)?;
+    // Normalize `..` so the path reads naturally in messages and tab names
+    let mut path = main_dir.to_path_buf();
+    for component in expanded.components() {
+        match component {
+            std::path::Component::ParentDir => {
+                path.pop();
+            }
+            std::path::Component::CurDir => {}
+            other => path.push(other),
+        }
+    }
+    path
+}
 
-    let err = Command::new("zellij")
-        .args(["--layout", &layout_path.to_string_lossy()])
-        .exec();
+/// Reserve the worktree's ports so parallel dev servers don't collide,
+/// returning them as env vars
+fn allocate_ports(worktree_path: &Path, config: &Config) -> Vec<(String, String)> {
+    match PortStore::new().allocate(worktree_path, config.ports.base, config.ports.count) {
+        Ok(block) => block.env_vars(),
+        Err(e) => {
+            eprintln!("Warning: Could not allocate ports: {}", e);
+            Vec::new()
+        }
+    }
+}
 
-    Err(Error::new(
-        ErrorKind::Other,
-        format!("Failed to exec zellij: {}", err),
-    ))
+/// Describe a worktree for its lifecycle hooks
+fn hook_context(worktree_path: &Path, branch: Option<&str>) -> HookContext {
+    HookContext {
+        worktree: worktree_path.to_path_buf(),
+        branch: branch.map(str::to_string),
+        default_branch: get_default_branch().ok(),
+        repo: list_worktrees()
+            .ok()
+            .and_then(|wts| wts.into_iter().next())
+            .map(|wt| wt.path),
+        env: PortStore::new()
+            .get(worktree_path)
+            .map(|block| block.env_vars())
+            .unwrap_or_default(),
+    }
+}
+
+pub struct RemoveOptions {
+    /// Discard uncommitted changes and unmerged commits
+    pub force: bool,
+    pub keep_branch: bool,
+    /// Skip confirmation prompts
+    pub yes: bool,
 }
 
-fn cmd_remove() -> io::Result<()> {
+fn cmd_remove(branches: &[String], opts: &RemoveOptions) -> io::Result<()> {
     let current_dir = std::env::current_dir()?;
     let worktrees = list_worktrees()?;
 
-    // Find current worktree
-    let current_wt = worktrees
-        .iter()
-        .find(|w| w.path == current_dir)
-        .ok_or_else(|| Error::new(ErrorKind::Other, "Not in a worktree"))?;
-
-    let branch = current_wt
-        .branch
-        .as_ref()
-        .ok_or_else(|| Error::new(ErrorKind::Other, "Cannot determine current branch"))?;
-
-    // Find main worktree (first non-bare one, or the bare one)
+    // The main worktree is always listed first
     let main_wt = worktrees
-        .iter()
-        .find(|w| !w.is_bare && w.path != current_dir)
-        .or_else(|| worktrees.iter().find(|w| w.is_bare))
+        .first()
         .ok_or_else(|| Error::new(ErrorKind::Other, "Cannot find main worktree"))?;
 
-    // Check if dirty
-    if is_dirty(&current_dir) {
+    let targets: Vec<&Worktree> = if branches.is_empty() {
+        let current_wt = worktrees
+            .iter()
+            .find(|w| w.path == current_dir)
+            .ok_or_else(|| Error::new(ErrorKind::Other, "Not in a worktree"))?;
+        vec![current_wt]
+    } else {
+        find_branches(&worktrees, branches)?
+    };
+
+    let main_path = main_wt.path.clone();
+
+    // Run from the main worktree so removing the current one doesn't pull the
+    // directory out from under us
+    std::env::set_current_dir(&main_path)?;
+
+    let mut removed = Vec::new();
+    let mut failures = 0;
+    for wt in targets {
+        if wt.path == main_path {
+            eprintln!("Error: Cannot remove the main worktree");
+            failures += 1;
+            continue;
+        }
+        match remove_worktree(wt, opts) {
+            Ok(true) => removed.push(wt.path.clone()),
+            Ok(false) => {}
+            Err(e) => {
+                let name = wt.branch.as_deref().unwrap_or("(detached)");
+                eprintln!("Error: {}: {}", name, e);
+                failures += 1;
+            }
+        }
+    }
+
+    let removed_current = close_tabs(&removed, &current_dir);
+
+    if failures > 0 {
         return Err(Error::new(
             ErrorKind::Other,
-            "Worktree has uncommitted changes. Commit or stash first.",
+            format!("Failed to remove {} worktree(s)", failures),
         ));
     }
 
-    let main_path = main_wt.path.clone();
-    let branch_to_delete = branch.clone();
-    let path_to_remove = current_dir.clone();
+    if removed_current {
+        exec_shell(&main_path)?;
+    }
+    Ok(())
+}
 
-    // Change to main worktree first
-    std::env::set_current_dir(&main_path)?;
+/// Look up the worktrees of `branches`, failing on the first one without
+fn find_branches<'a>(worktrees: &'a [Worktree], branches: &[String]) -> io::Result<Vec<&'a Worktree>> {
+    branches
+        .iter()
+        .map(|branch| {
+            worktrees
+                .iter()
+                .find(|w| w.branch.as_deref() == Some(branch.as_str()))
+                .ok_or_else(|| {
+                    Error::new(
+                        ErrorKind::Other,
+                        format!("No worktree for branch '{}'", branch),
+                    )
+                })
+        })
+        .collect()
+}
 
-    // Remove worktree
-    let output = Command::new("git")
-        .args(["worktree", "remove"])
-        .arg(&path_to_remove)
-        .output()?;
+/// Close the tabs of removed worktrees. Returns whether the current worktree
+/// was among them.
+fn close_tabs(removed: &[PathBuf], current_dir: &Path) -> bool {
+    // Close the current tab at the very end: closing it may take this process
+    // down with its pane
+    let removed_current = removed.iter().any(|p| p == current_dir);
+    for path in removed.iter().filter(|p| *p != current_dir) {
+        let _ = zellij::close_tab(&tab_name(path));
+    }
+    if removed_current {
+        let _ = zellij::close_tab(&tab_name(current_dir));
+    }
+    removed_current
+}
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(Error::new(ErrorKind::Other, stderr.to_string()));
+/// Remove a single worktree and its branch. Returns `false` if the user
+/// declined to discard its changes.
+fn remove_worktree(wt: &Worktree, opts: &RemoveOptions) -> io::Result<bool> {
+    let name = wt.branch.as_deref().unwrap_or("(detached)");
+
+    if wt.path.exists() && is_dirty(&wt.path) {
+        if !opts.force {
+            return Err(Error::new(
+                ErrorKind::Other,
+                "Worktree has uncommitted changes. Commit or stash first, or use --force.",
+            ));
+        }
+        if !opts.yes && !confirm(&format!("Discard uncommitted changes in '{}'?", name)) {
+            println!("Skipped '{}'", name);
+            return Ok(false);
+        }
     }
 
-    // Delete branch
-    let output = Command::new("git")
-        .args(["branch", "-d", &branch_to_delete])
-        .output()?;
+    // Teardown (e.g. dropping a per-worktree database) runs while the
+    // worktree still exists; if it's already gone, run from the main worktree
+    let hook_cwd = if wt.path.exists() {
+        wt.path.clone()
+    } else {
+        std::env::current_dir()?
+    };
+    let config = Config::load(&hook_cwd)?;
+    let ctx = hook_context(&wt.path, wt.branch.as_deref());
+    hooks::run("pre_remove", config.hooks.pre_remove.as_deref(), &ctx, &hook_cwd)?;
+
+    let mut args = vec!["worktree", "remove"];
+    if opts.force {
+        args.push("--force");
+    }
+    let output = Command::new("git").args(&args).arg(&wt.path).output()?;
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
-        eprintln!("Warning: Could not delete branch: {}", stderr.trim());
+        return Err(Error::new(ErrorKind::Other, stderr.trim().to_string()));
     }
 
-    println!("Removed worktree and branch '{}'", branch_to_delete);
-    exec_shell(&main_path)?;
-    Ok(())
+    // Only forget the worktree's agents and ports once it's really gone
+    let unregistered = InstanceStore::new().unregister_within(&wt.path)?;
+    let count = unregistered.len();
+    webhook::emit(unregistered.into_iter().map(Change::Unregistered).collect());
+    PortStore::new().release(&wt.path)?;
+
+    let mut summary = format!("Removed worktree '{}'", name);
+
+    if let Some(branch) = wt.branch.as_deref().filter(|_| !opts.keep_branch) {
+        let mut output = Command::new("git").args(["branch", "-d", branch]).output()?;
+
+        // Unmerged commits are only thrown away with --force, after confirmation
+        let unmerged = String::from_utf8_lossy(&output.stderr).contains("not fully merged");
+        let mut kept = false;
+        if !output.status.success() && unmerged && opts.force {
+            if opts.yes || confirm(&format!("Delete unmerged branch '{}'?", branch)) {
+                output = Command::new("git").args(["branch", "-D", branch]).output()?;
+            } else {
+                println!("Kept branch '{}'", branch);
+                kept = true;
+            }
+        }
+
+        if output.status.success() {
+            summary.push_str(" and its branch");
+        } else if !kept {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            eprintln!("Warning: Could not delete branch: {}", stderr.trim());
+        }
+    }
+
+    if count > 0 {
+        summary.push_str(&format!(" ({} instance(s) unregistered)", count));
+    }
+    println!("{}", summary);
+    Ok(true)
+}
+
+/// Ask a yes/no question on the terminal, defaulting to no
+fn confirm(prompt: &str) -> bool {
+    use std::io::Write;
+
+    print!("{} [y/N] ", prompt);
+    let _ = io::stdout().flush();
+
+    let mut answer = String::new();
+    if io::stdin().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
 }
 
 fn cmd_merge() -> io::Result<()> {
@@ -206,7 +793,15 @@ fn cmd_merge() -> io::Result<()> {
         .find(|w| w.path == current_dir)
         .ok_or_else(|| Error::new(ErrorKind::Other, "Not in a worktree"))?;
 
-    let branch = current_wt
+    let main_path = merge_worktree(current_wt, &worktrees)?;
+    exec_shell(&main_path)?;
+    Ok(())
+}
+
+/// Squash-merge a worktree's branch into the default branch, then remove the
+/// worktree and branch. Returns the main worktree's path.
+fn merge_worktree(wt: &Worktree, worktrees: &[Worktree]) -> io::Result<PathBuf> {
+    let branch = wt
         .branch
         .as_ref()
         .ok_or_else(|| Error::new(ErrorKind::Other, "Cannot determine current branch"))?
@@ -222,7 +817,7 @@ fn cmd_merge() -> io::Result<()> {
     }
 
     // Check if dirty
-    if is_dirty(&current_dir) {
+    if is_dirty(&wt.path) {
         return Err(Error::new(
             ErrorKind::Other,
             "Worktree has uncommitted changes. Commit or stash first.",
@@ -241,7 +836,13 @@ fn cmd_merge() -> io::Result<()> {
         })?;
 
     let main_path = main_wt.path.clone();
-    let path_to_remove = current_dir.clone();
+
+    // Load hooks before the worktree (and its config) is removed
+    let config = Config::load(&wt.path)?;
+    // Deleting the branch drops its config, including the linked issue
+    let issue = beads::linked_issue(&wt.path, &branch);
+    let ctx = hook_context(&wt.path, Some(&branch));
+    hooks::run("pre_merge", config.hooks.pre_merge.as_deref(), &ctx, &wt.path)?;
 
     // Change to main worktree
     std::env::set_current_dir(&main_path)?;
@@ -259,15 +860,18 @@ fn cmd_merge() -> io::Result<()> {
         ));
     }
 
-    // Commit the squashed changes
-    let output = Command::new("git")
-        .args(["commit", "-m", &format!("Merge branch '{}'", branch)])
-        .output()?;
+    // Commit the squashed changes, unless they're already on the default branch
+    let committed = !Command::new("git")
+        .args(["diff", "--cached", "--quiet"])
+        .status()?
+        .success();
+    if committed {
+        let output = Command::new("git")
+            .args(["commit", "-m", &format!("Merge branch '{}'", branch)])
+            .output()?;
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        // Check if it's just "nothing to commit"
-        if !stderr.contains("nothing to commit") {
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
             return Err(Error::new(
                 ErrorKind::Other,
                 format!("Commit failed: {}", stderr),
@@ -275,32 +879,405 @@ fn cmd_merge() -> io::Result<()> {
         }
     }
 
-    // Remove worktree
-    let output = Command::new("git")
-        .args(["worktree", "remove"])
-        .arg(&path_to_remove)
-        .output()?;
+    let merge_commit = git_output(&main_path, &["rev-parse", "HEAD"]).unwrap_or_default();
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        eprintln!("Warning: Could not remove worktree: {}", stderr.trim());
+    // The branch was merged as a squash, so git can't tell it's merged
+    let opts = RemoveOptions {
+        force: true,
+        keep_branch: false,
+        yes: true,
+    };
+    let removed = match remove_worktree(wt, &opts) {
+        Ok(removed) => removed,
+        Err(e) => {
+            eprintln!("Warning: Could not remove worktree: {}", e);
+            false
+        }
+    };
+
+    // The hook runs either way; it can tell from MAESTRO_WORKTREE_REMOVED
+    let mut ctx = hook_context(&wt.path, Some(&branch));
+    ctx.env.push(("MAESTRO_MERGE_COMMIT".to_string(), merge_commit.clone()));
+    ctx.env.push((
+        "MAESTRO_WORKTREE_REMOVED".to_string(),
+        if removed { "1" } else { "0" }.to_string(),
+    ));
+    hooks::run_post("post_merge", config.hooks.post_merge.as_deref(), &ctx, &main_path);
+
+    if removed {
+        println!("Merged '{}' into '{}' and cleaned up", branch, default_branch);
+    } else {
+        println!("Merged '{}' into '{}'", branch, default_branch);
+    }
+    if let Some(issue) = issue
+        && confirm(&format!("Close issue {}?", issue))
+    {
+        let reason = if committed {
+            format!("Merged in {}", merge_commit)
+        } else {
+            format!("Merged '{}' with no changes left to commit", branch)
+        };
+        match beads::close_issue(&main_path, &issue, &reason) {
+            Ok(()) => println!("Closed issue {}", issue),
+            Err(e) => eprintln!("Warning: Could not close issue {}: {}", issue, e),
+        }
     }
+    Ok(main_path)
+}
 
-    // Delete branch
-    let output = Command::new("git")
-        .args(["branch", "-d", &branch])
+/// One attempt at a task, as compared by `wt compare`
+struct Attempt<'a> {
+    wt: &'a Worktree,
+    branch: &'a str,
+    dirty: bool,
+    diff: Option<DiffStat>,
+    check: Option<CheckResult>,
+    transcript: Option<TranscriptSummary>,
+}
+
+struct CheckResult {
+    passed: bool,
+    duration: Duration,
+    /// Last non-empty line of output, usually the test summary
+    summary: Option<String>,
+}
+
+fn cmd_compare(branches: &[String], run_check: bool) -> io::Result<()> {
+    let current_dir = std::env::current_dir()?;
+    let worktrees = list_worktrees()?;
+    let default_branch = get_default_branch()?;
+
+    let mut attempts = Vec::new();
+    for (wt, branch) in find_branches(&worktrees, branches)?.into_iter().zip(branches) {
+        if !wt.path.exists() {
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!("Worktree for '{}' is missing: {}", branch, wt.path.display()),
+            ));
+        }
+        let config = Config::load(&wt.path)?;
+        let check = match config.check.command.as_deref().filter(|_| run_check) {
+            Some(cmd) => {
+                eprintln!("Running check in '{}': {}", branch, cmd);
+                Some(run_check_command(cmd, wt, branch)?)
+            }
+            None => None,
+        };
+        attempts.push(Attempt {
+            wt,
+            branch,
+            dirty: is_dirty(&wt.path),
+            diff: git_output(&wt.path, &["merge-base", &default_branch, "HEAD"])
+                .and_then(|base| git_output(&wt.path, &["diff", "--shortstat", &base]))
+                .map(|out| parse_shortstat(&out)),
+            check,
+            transcript: transcript::summarize_dir(&wt.path),
+        });
+    }
+
+    println!("Comparing {} attempts against '{}'", attempts.len(), default_branch);
+    for (i, attempt) in attempts.iter().enumerate() {
+        let dirty = if attempt.dirty { "  [dirty]" } else { "" };
+        println!();
+        println!("[{}] {}  {}{}", i + 1, attempt.branch, attempt.wt.path.display(), dirty);
+        if let Some(diff) = &attempt.diff {
+            println!("    changes  {}", format_diff(diff));
+        }
+        if let Some(check) = &attempt.check {
+            let verdict = if check.passed { "passed" } else { "FAILED" };
+            let mut line = format!("{} in {:.1}s", verdict, check.duration.as_secs_f64());
+            if let Some(summary) = &check.summary {
+                line.push_str(": ");
+                line.push_str(summary);
+            }
+            println!("    check    {}", line);
+        }
+        match &attempt.transcript {
+            Some(summary) => println!(
+                "    tokens   {} in, {} out (~${:.2})",
+                transcript::format_tokens(summary.tokens.total_input()),
+                transcript::format_tokens(summary.tokens.output),
+                summary.cost_usd
+            ),
+            None => println!("    tokens   no transcripts"),
+        }
+    }
+
+    println!();
+    println!("Between attempts (committed changes):");
+    for (i, a) in attempts.iter().enumerate() {
+        for b in &attempts[i + 1..] {
+            let diff = git_output(&a.wt.path, &["diff", "--shortstat", a.branch, b.branch])
+                .map(|out| parse_shortstat(&out))
+                .unwrap_or_default();
+            println!("  {} ↔ {}  {}", a.branch, b.branch, format_diff(&diff));
+        }
+    }
+    println!();
+
+    let Some(chosen) = choose(&format!("Merge which attempt? [1-{}, Enter to skip]", attempts.len()), attempts.len())
+    else {
+        println!("Nothing merged");
+        return Ok(());
+    };
+
+    let merged = &attempts[chosen];
+    let main_path = merge_worktree(merged.wt, &worktrees)?;
+    let mut removed = vec![merged.wt.path.clone()];
+
+    let others: Vec<&Attempt> = attempts.iter().filter(|a| a.branch != merged.branch).collect();
+    let names: Vec<&str> = others.iter().map(|a| a.branch).collect();
+    if confirm(&format!("Remove the other attempts ({})?", names.join(", "))) {
+        // They were never merged, so their branches can only go with --force
+        let opts = RemoveOptions {
+            force: true,
+            keep_branch: false,
+            yes: true,
+        };
+        for attempt in others {
+            match remove_worktree(attempt.wt, &opts) {
+                Ok(true) => removed.push(attempt.wt.path.clone()),
+                Ok(false) => {}
+                Err(e) => eprintln!("Error: {}: {}", attempt.branch, e),
+            }
+        }
+    }
+
+    if close_tabs(&removed, &current_dir) {
+        exec_shell(&main_path)?;
+    }
+    Ok(())
+}
+
+/// Run the check command in an attempt's worktree, capturing its output
+fn run_check_command(cmd: &str, wt: &Worktree, branch: &str) -> io::Result<CheckResult> {
+    let ctx = hook_context(&wt.path, Some(branch));
+    let start = Instant::now();
+    let output = Command::new("sh")
+        .args(["-c", cmd])
+        .current_dir(&wt.path)
+        .envs(ctx.env_vars("check"))
+        .stdin(Stdio::null())
         .output()?;
+    let duration = start.elapsed();
+
+    // Test runners print their summary last, failures usually on stderr
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let (first, second) = if output.status.success() {
+        (&stdout, &stderr)
+    } else {
+        (&stderr, &stdout)
+    };
+    Ok(CheckResult {
+        passed: output.status.success(),
+        duration,
+        summary: last_line(first).or_else(|| last_line(second)),
+    })
+}
+
+fn last_line(output: &str) -> Option<String> {
+    output
+        .lines()
+        .map(str::trim)
+        .rfind(|line| !line.is_empty())
+        .map(str::to_string)
+}
+
+fn format_diff(diff: &DiffStat) -> String {
+    if diff.files == 0 {
+        return "no changes".to_string();
+    }
+    let files = if diff.files == 1 { "file" } else { "files" };
+    format!("{} {} +{} -{}", diff.files, files, diff.insertions, diff.deletions)
+}
+
+/// Ask for a number between 1 and `count`; returns its index, or `None` if
+/// the answer is empty or out of range
+fn choose(prompt: &str, count: usize) -> Option<usize> {
+    use std::io::Write;
+
+    print!("{} ", prompt);
+    let _ = io::stdout().flush();
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).ok()?;
+    let n: usize = answer.trim().parse().ok()?;
+    (1..=count).contains(&n).then(|| n - 1)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum PruneReason {
+    /// The worktree directory no longer exists
+    Missing,
+    /// The branch is already merged into the default branch
+    Merged(String),
+    /// No commits or index changes for this many days
+    Stale(u64),
+}
+
+impl std::fmt::Display for PruneReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PruneReason::Missing => write!(f, "directory missing"),
+            PruneReason::Merged(branch) => write!(f, "merged into {}", branch),
+            PruneReason::Stale(days) => write!(f, "untouched for {} days", days),
+        }
+    }
+}
+
+fn cmd_prune(days: Option<u64>, yes: bool) -> io::Result<()> {
+    let current_dir = std::env::current_dir()?;
+    let worktrees = list_worktrees()?;
+    let default_branch = get_default_branch()?;
+    let instances = InstanceStore::new().load();
+    let now = unix_now();
+
+    let mut candidates = Vec::new();
+    // The main worktree is always listed first and never pruned
+    for wt in worktrees.iter().skip(1) {
+        if wt.is_bare || wt.path == current_dir {
+            continue;
+        }
 
+        let has_agent = instances
+            .iter()
+            .any(|i| i.cwd.as_deref().is_some_and(|cwd| Path::new(cwd).starts_with(&wt.path)));
+        if has_agent {
+            continue;
+        }
+
+        let reason = if !wt.path.exists() {
+            PruneReason::Missing
+        } else {
+            let name = wt.branch.as_deref().unwrap_or("(detached)");
+            let merged = wt
+                .branch
+                .as_deref()
+                .is_some_and(|branch| is_merged(&wt.path, branch, &default_branch));
+            let reason = if merged {
+                Some(PruneReason::Merged(default_branch.clone()))
+            } else {
+                days.and_then(|days| {
+                    let idle_days = now.saturating_sub(last_activity(&wt.path)) / 86400;
+                    (idle_days >= days).then_some(PruneReason::Stale(idle_days))
+                })
+            };
+            let Some(reason) = reason else {
+                continue;
+            };
+            if is_dirty(&wt.path) {
+                println!("Skipping '{}' ({}): has uncommitted changes", name, reason);
+                continue;
+            }
+            reason
+        };
+
+        candidates.push((wt, reason));
+    }
+
+    if candidates.is_empty() {
+        println!("Nothing to prune");
+    } else {
+        let branch_width = candidates
+            .iter()
+            .map(|(wt, _)| wt.branch.as_deref().unwrap_or("(detached)").chars().count())
+            .max()
+            .unwrap_or(0);
+        for (wt, reason) in &candidates {
+            println!(
+                "  {:<branch_width$}  {}  ({})",
+                wt.branch.as_deref().unwrap_or("(detached)"),
+                wt.path.display(),
+                reason
+            );
+        }
+
+        if !yes && !confirm(&format!("Remove {} worktree(s)?", candidates.len())) {
+            return Ok(());
+        }
+
+        let opts = RemoveOptions {
+            force: false,
+            keep_branch: false,
+            yes: true,
+        };
+        for (wt, _) in &candidates {
+            match remove_worktree(wt, &opts) {
+                Ok(_) => {
+                    let _ = zellij::close_tab(&tab_name(&wt.path));
+                }
+                Err(e) => eprintln!(
+                    "Error: {}: {}",
+                    wt.branch.as_deref().unwrap_or("(detached)"),
+                    e
+                ),
+            }
+        }
+    }
+
+    // Clean up administrative files of worktrees deleted behind git's back
+    let output = Command::new("git").args(["worktree", "prune"]).output()?;
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
-        eprintln!("Warning: Could not delete branch: {}", stderr.trim());
+        eprintln!("Warning: git worktree prune failed: {}", stderr.trim());
     }
 
-    println!("Merged '{}' into '{}' and cleaned up", branch, default_branch);
-    exec_shell(&main_path)?;
     Ok(())
 }
 
+/// Whether the worktree's HEAD is merged into `default_branch`, either as an
+/// ancestor or with every commit's patch already applied (e.g. cherry-picked).
+///
+/// A branch that never moved since it was created is also an ancestor of the
+/// default branch, so ancestry only counts once the branch has had commits.
+fn is_merged(path: &Path, branch: &str, default_branch: &str) -> bool {
+    let is_ancestor = Command::new("git")
+        .arg("-C")
+        .arg(path)
+        .args(["merge-base", "--is-ancestor", "HEAD", default_branch])
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(false);
+
+    let branch_ref = format!("refs/heads/{}", branch);
+    let has_moved = git_output(path, &["reflog", "show", "--format=%H", &branch_ref])
+        .is_some_and(|out| out.lines().count() > 1);
+
+    (is_ancestor && has_moved)
+        || git_output(path, &["cherry", default_branch, "HEAD"])
+            .is_some_and(|out| all_patches_upstream(&out))
+}
+
+/// Whether `git cherry` reports every commit as already upstream
+fn all_patches_upstream(cherry: &str) -> bool {
+    let mut lines = cherry.lines().filter(|l| !l.trim().is_empty()).peekable();
+    lines.peek().is_some() && lines.all(|l| l.starts_with('-'))
+}
+
+/// Unix timestamp of the most recent commit, checkout or reset in the worktree
+fn last_activity(path: &Path) -> u64 {
+    let commit = git_output(path, &["log", "-1", "--format=%ct"])
+        .and_then(|out| out.parse().ok())
+        .unwrap_or(0);
+
+    // Reflog selector with unix dates, e.g. "HEAD@{1700000000}"
+    let reflog = git_output(path, &["log", "-g", "-1", "--date=unix", "--format=%gd", "HEAD"])
+        .and_then(|out| parse_reflog_time(&out))
+        .unwrap_or(0);
+
+    commit.max(reflog)
+}
+
+fn parse_reflog_time(selector: &str) -> Option<u64> {
+    selector
+        .rsplit_once("@{")?
+        .1
+        .strip_suffix('}')?
+        .parse()
+        .ok()
+}
+
 fn list_worktrees() -> io::Result<Vec<Worktree>> {
     let output = Command::new("git")
         .args(["worktree", "list", "--porcelain"])
@@ -311,14 +1288,17 @@ fn list_worktrees() -> io::Result<Vec<Worktree>> {
         return Err(Error::new(ErrorKind::Other, stderr.to_string()));
     }
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(parse_worktree_list(&String::from_utf8_lossy(&output.stdout)))
+}
+
+fn parse_worktree_list(porcelain: &str) -> Vec<Worktree> {
     let mut worktrees = Vec::new();
     let mut current_path: Option<PathBuf> = None;
     let mut current_branch: Option<String> = None;
     let mut is_bare = false;
 
-    for line in stdout.lines() {
-        if line.starts_with("worktree ") {
+    for line in porcelain.lines() {
+        if let Some(path) = line.strip_prefix("worktree ") {
             // Save previous worktree if exists
             if let Some(path) = current_path.take() {
                 worktrees.push(Worktree {
@@ -328,9 +1308,9 @@ fn list_worktrees() -> io::Result<Vec<Worktree>> {
                 });
                 is_bare = false;
             }
-            current_path = Some(PathBuf::from(&line[9..]));
-        } else if line.starts_with("branch refs/heads/") {
-            current_branch = Some(line[18..].to_string());
+            current_path = Some(PathBuf::from(path));
+        } else if let Some(branch) = line.strip_prefix("branch refs/heads/") {
+            current_branch = Some(branch.to_string());
         } else if line == "bare" {
             is_bare = true;
         }
@@ -345,7 +1325,73 @@ fn list_worktrees() -> io::Result<Vec<Worktree>> {
         });
     }
 
-    Ok(worktrees)
+    worktrees
+}
+
+/// Run a read-only git query in `dir`, returning trimmed stdout on success
+fn git_output(dir: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Parse `git log --format=%ct%x09%s` output
+fn parse_commit_line(line: &str) -> Option<CommitInfo> {
+    let (timestamp, subject) = line.split_once('\t')?;
+    Some(CommitInfo {
+        subject: subject.to_string(),
+        timestamp: timestamp.trim().parse().ok()?,
+    })
+}
+
+/// Parse `git rev-list --left-right --count` output into (left, right)
+fn parse_left_right(output: &str) -> Option<(u32, u32)> {
+    let mut parts = output.split_whitespace();
+    let left = parts.next()?.parse().ok()?;
+    let right = parts.next()?.parse().ok()?;
+    Some((left, right))
+}
+
+/// Parse `git diff --shortstat` output, e.g. " 3 files changed, 10 insertions(+), 2 deletions(-)"
+fn parse_shortstat(output: &str) -> DiffStat {
+    let mut stat = DiffStat::default();
+    for part in output.split(',') {
+        let mut words = part.split_whitespace();
+        let (Some(count), Some(kind)) = (words.next(), words.next()) else {
+            continue;
+        };
+        let Ok(count) = count.parse() else {
+            continue;
+        };
+        if kind.starts_with("file") {
+            stat.files = count;
+        } else if kind.starts_with("insertion") {
+            stat.insertions = count;
+        } else if kind.starts_with("deletion") {
+            stat.deletions = count;
+        }
+    }
+    stat
+}
+
+/// Format an age in seconds as a short relative time, e.g. "3h ago"
+pub fn format_age(secs: u64) -> String {
+    match secs {
+        0..60 => "just now".to_string(),
+        60..3600 => format!("{}m ago", secs / 60),
+        3600..86400 => format!("{}h ago", secs / 3600),
+        86400..604800 => format!("{}d ago", secs / 86400),
+        _ => format!("{}w ago", secs / 604800),
+    }
 }
 
 fn is_dirty(path: &Path) -> bool {
@@ -369,6 +1415,46 @@ fn get_repo_root() -> io::Result<PathBuf> {
     Ok(PathBuf::from(path))
 }
 
+/// Name of the repository, taken from the main worktree even when called
+/// from a linked worktree
+fn get_repo_name() -> io::Result<String> {
+    repo_name(Path::new("."))
+}
+
+/// Name of the repository containing `dir`
+fn repo_name(dir: &Path) -> io::Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["rev-parse", "--path-format=absolute", "--git-common-dir"])
+        .output()?;
+
+    if !output.status.success() {
+        return Err(Error::new(ErrorKind::Other, "Not in a git repository"));
+    }
+
+    let common_dir = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    // `<repo>/.git` for regular repositories, `<repo>.git` for bare ones
+    let repo_dir = if common_dir.file_name().is_some_and(|n| n == ".git") {
+        common_dir.parent().unwrap_or(&common_dir)
+    } else {
+        &common_dir
+    };
+
+    repo_dir
+        .file_name()
+        .map(|n| n.to_string_lossy().trim_end_matches(".git").to_string())
+        .ok_or_else(|| Error::new(ErrorKind::Other, "Cannot determine repo name"))
+}
+
+/// Repository name and checked-out branch of the worktree containing `path`
+pub fn git_context(path: &Path) -> (Option<String>, Option<String>) {
+    let repo = repo_name(path).ok();
+    let branch = git_output(path, &["rev-parse", "--abbrev-ref", "HEAD"])
+        .filter(|b| !b.is_empty() && b != "HEAD");
+    (repo, branch)
+}
+
 fn get_default_branch() -> io::Result<String> {
     // Try to get from remote
     let output = Command::new("git")
@@ -399,9 +1485,16 @@ fn get_default_branch() -> io::Result<String> {
     ))
 }
 
+/// Replace the process with a shell in `path`; a no-op when not attached to a
+/// terminal (e.g. when invoked from the Zellij plugin)
 fn exec_shell(path: &Path) -> io::Result<()> {
+    use std::io::IsTerminal;
     use std::os::unix::process::CommandExt;
 
+    if !io::stdout().is_terminal() {
+        return Ok(());
+    }
+
     let shell = std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string());
 
     std::env::set_current_dir(path)?;
@@ -413,3 +1506,102 @@ fn exec_shell(path: &Path) -> io::Result<()> {
         format!("Failed to exec shell: {}", err),
     ))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_worktree_list() {
+        let porcelain = "worktree /home/user/project
+HEAD 1111111111111111111111111111111111111111
+branch refs/heads/main
+
+worktree /home/user/project.feature
+HEAD 2222222222222222222222222222222222222222
+branch refs/heads/feature
+
+worktree /home/user/project.detached
+HEAD 3333333333333333333333333333333333333333
+detached
+";
+        let worktrees = parse_worktree_list(porcelain);
+
+        assert_eq!(worktrees.len(), 3);
+        assert_eq!(worktrees[0].path, PathBuf::from("/home/user/project"));
+        assert_eq!(worktrees[0].branch.as_deref(), Some("main"));
+        assert_eq!(worktrees[1].branch.as_deref(), Some("feature"));
+        assert!(worktrees[2].branch.is_none());
+        assert!(!worktrees[2].is_bare);
+    }
+
+    #[test]
+    fn test_parse_shortstat() {
+        let stat = parse_shortstat(" 3 files changed, 10 insertions(+), 2 deletions(-)");
+        assert_eq!(
+            stat,
+            DiffStat {
+                files: 3,
+                insertions: 10,
+                deletions: 2
+            }
+        );
+
+        let stat = parse_shortstat(" 1 file changed, 1 deletion(-)");
+        assert_eq!(stat.files, 1);
+        assert_eq!(stat.insertions, 0);
+        assert_eq!(stat.deletions, 1);
+
+        assert_eq!(parse_shortstat(""), DiffStat::default());
+    }
+
+    #[test]
+    fn test_parse_commit_and_counts() {
+        let commit = parse_commit_line("1700000000\tFix the thing\twith tabs").unwrap();
+        assert_eq!(commit.timestamp, 1700000000);
+        assert_eq!(commit.subject, "Fix the thing\twith tabs");
+
+        assert_eq!(parse_left_right("4\t2"), Some((4, 2)));
+        assert_eq!(parse_left_right(""), None);
+    }
+
+    #[test]
+    fn test_all_patches_upstream() {
+        assert!(all_patches_upstream("- abc123\n- def456\n"));
+        assert!(!all_patches_upstream("- abc123\n+ def456\n"));
+        assert!(!all_patches_upstream(""));
+    }
+
+    #[test]
+    fn test_parse_reflog_time() {
+        assert_eq!(parse_reflog_time("HEAD@{1700000000}"), Some(1700000000));
+        assert_eq!(parse_reflog_time("HEAD@{0}x"), None);
+        assert_eq!(parse_reflog_time(""), None);
+    }
+
+    #[test]
+    fn test_expand_path_template() {
+        let main = Path::new("/home/user/code/project");
+        assert_eq!(
+            expand_path_template(DEFAULT_PATH_TEMPLATE, main, "project", "feature"),
+            PathBuf::from("/home/user/code/project.feature")
+        );
+        assert_eq!(
+            expand_path_template(".worktrees/{branch}", main, "project", "fix/bug"),
+            PathBuf::from("/home/user/code/project/.worktrees/fix/bug")
+        );
+        assert_eq!(
+            expand_path_template("/tmp/wt/{repo}-{branch}", main, "project", "feature"),
+            PathBuf::from("/tmp/wt/project-feature")
+        );
+    }
+
+    #[test]
+    fn test_format_age() {
+        assert_eq!(format_age(5), "just now");
+        assert_eq!(format_age(120), "2m ago");
+        assert_eq!(format_age(3 * 3600), "3h ago");
+        assert_eq!(format_age(2 * 86400), "2d ago");
+        assert_eq!(format_age(21 * 86400), "3w ago");
+    }
+}
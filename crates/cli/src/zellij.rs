@@ -0,0 +1,195 @@
+use std::io::{self, Error, ErrorKind};
+use std::path::Path;
+use std::process::Command;
+
+/// Whether we're running inside a Zellij session
+pub fn inside_session() -> bool {
+    std::env::var_os("ZELLIJ").is_some()
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum SessionState {
+    Running,
+    Exited,
+    Missing,
+}
+
+/// Open a layout as a tab, reusing an existing tab with the same name.
+///
+/// Inside Zellij the tab is added to the current session. Outside Zellij we
+/// attach to (or create) `session` and open the tab there.
+pub fn open_layout(layout_path: &Path, tab_name: &str, cwd: &Path, session: &str) -> io::Result<()> {
+    if inside_session() {
+        return open_tab(None, layout_path, tab_name);
+    }
+
+    std::env::set_current_dir(cwd)?;
+
+    match session_state(session)? {
+        SessionState::Running => {}
+        SessionState::Exited => {
+            // Resurrect in the background so the tab can be added before attaching
+            run_zellij(&["attach", "--create-background", session])?;
+        }
+        SessionState::Missing => {
+            return exec_zellij(&[
+                "--session",
+                session,
+                "--new-session-with-layout",
+                &layout_path.to_string_lossy(),
+            ]);
+        }
+    }
+
+    open_tab(Some(session), layout_path, tab_name)?;
+    exec_zellij(&["attach", session])
+}
+
+fn open_tab(session: Option<&str>, layout_path: &Path, tab_name: &str) -> io::Result<()> {
+    if tab_names(session)?.iter().any(|name| name == tab_name) {
+        return run_action(session, &["go-to-tab-name", tab_name]);
+    }
+    run_action(session, &["new-tab", "--layout", &layout_path.to_string_lossy()])
+}
+
+/// Close the tab named `tab_name` in the current session, if there is one
+pub fn close_tab(tab_name: &str) -> io::Result<bool> {
+    if !inside_session() || !tab_names(None)?.iter().any(|name| name == tab_name) {
+        return Ok(false);
+    }
+    run_action(None, &["go-to-tab-name", tab_name])?;
+    run_action(None, &["close-tab"])?;
+    Ok(true)
+}
+
+/// Names of the tabs a layout would be opened next to: the current session's
+/// inside Zellij, else `session`'s if it's running
+pub fn open_tab_names(session: &str) -> io::Result<Vec<String>> {
+    if inside_session() {
+        return tab_names(None);
+    }
+    match session_state(session)? {
+        SessionState::Running => tab_names(Some(session)),
+        SessionState::Exited | SessionState::Missing => Ok(Vec::new()),
+    }
+}
+
+fn tab_names(session: Option<&str>) -> io::Result<Vec<String>> {
+    let output = action_command(session, &["query-tab-names"]).output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(Error::new(ErrorKind::Other, stderr.trim().to_string()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| line.trim().to_string())
+        .collect())
+}
+
+fn session_state(session: &str) -> io::Result<SessionState> {
+    let output = Command::new("zellij")
+        .args(["list-sessions", "--no-formatting"])
+        .output()?;
+
+    // `list-sessions` fails when there are no sessions at all
+    if !output.status.success() {
+        return Ok(SessionState::Missing);
+    }
+
+    Ok(parse_session_state(&String::from_utf8_lossy(&output.stdout), session))
+}
+
+fn parse_session_state(list: &str, session: &str) -> SessionState {
+    for line in list.lines() {
+        if line.split_whitespace().next() != Some(session) {
+            continue;
+        }
+        return if line.contains("EXITED") {
+            SessionState::Exited
+        } else {
+            SessionState::Running
+        };
+    }
+    SessionState::Missing
+}
+
+/// Derive a valid Zellij session name from a repository name
+pub fn session_name(repo_name: &str) -> String {
+    let name: String = repo_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    if name.is_empty() {
+        "maestro".to_string()
+    } else {
+        name
+    }
+}
+
+fn action_command(session: Option<&str>, args: &[&str]) -> Command {
+    let mut cmd = Command::new("zellij");
+    if let Some(session) = session {
+        cmd.args(["--session", session]);
+    }
+    cmd.arg("action").args(args);
+    cmd
+}
+
+fn run_action(session: Option<&str>, args: &[&str]) -> io::Result<()> {
+    let output = action_command(session, args).output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(Error::new(
+            ErrorKind::Other,
+            format!("zellij action {} failed: {}", args[0], stderr.trim()),
+        ));
+    }
+    Ok(())
+}
+
+fn run_zellij(args: &[&str]) -> io::Result<()> {
+    let output = Command::new("zellij").args(args).output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(Error::new(ErrorKind::Other, stderr.trim().to_string()));
+    }
+    Ok(())
+}
+
+fn exec_zellij(args: &[&str]) -> io::Result<()> {
+    use std::os::unix::process::CommandExt;
+
+    let err = Command::new("zellij").args(args).exec();
+
+    Err(Error::new(
+        ErrorKind::Other,
+        format!("Failed to exec zellij: {}", err),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_session_state() {
+        let list = "project [Created 2h ago] (current)\nother.feat [Created 1d ago] (EXITED - attach to resurrect)\n";
+
+        assert_eq!(parse_session_state(list, "project"), SessionState::Running);
+        assert_eq!(parse_session_state(list, "other.feat"), SessionState::Exited);
+        assert_eq!(parse_session_state(list, "proj"), SessionState::Missing);
+    }
+
+    #[test]
+    fn test_session_name() {
+        assert_eq!(session_name("maestro-ai"), "maestro-ai");
+        assert_eq!(session_name("my repo/x"), "my-repo-x");
+        assert_eq!(session_name(""), "maestro");
+    }
+}
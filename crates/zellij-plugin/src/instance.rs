@@ -6,6 +6,8 @@ pub enum InstanceStatus {
     #[default]
     Running,
     Waiting,
+    /// Blocked on a permission prompt
+    Permission,
 }
 
 impl InstanceStatus {
@@ -13,6 +15,7 @@ impl InstanceStatus {
         match self {
             InstanceStatus::Running => "⚙️",
             InstanceStatus::Waiting => "💬",
+            InstanceStatus::Permission => "🔒",
         }
     }
 }
@@ -22,12 +25,83 @@ pub struct ClaudeInstance {
     pub pane_id: u32,
     pub folder: String,
     pub status: InstanceStatus,
+    /// Agent kind (`claude`, `aider`, ...)
+    #[serde(default)]
+    pub agent: String,
+    /// Batch of parallel agents started together by `maestro fanout`
+    #[serde(default)]
+    pub batch: Option<String>,
+    /// Usage and latest messages from the Claude Code transcript
+    #[serde(default)]
+    pub transcript: Option<Transcript>,
+    /// Tool the agent is running
+    #[serde(default)]
+    pub current_tool: Option<ToolCall>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ToolCall {
+    /// e.g. `Bash: cargo test`
+    #[serde(default)]
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Transcript {
+    #[serde(default)]
+    pub cost_usd: f64,
+    #[serde(default)]
+    pub turns: u32,
+    #[serde(default)]
+    pub last_prompt: Option<String>,
+    #[serde(default)]
+    pub last_message: Option<String>,
+}
+
+impl ClaudeInstance {
+    /// Agent name to show next to the instance; Claude is the default and
+    /// isn't labelled
+    pub fn agent_label(&self) -> Option<&str> {
+        match self.agent.as_str() {
+            "" | "claude" => None,
+            agent => Some(agent),
+        }
+    }
+}
+
+/// `[limits]` caps and what counts against them
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Limits {
+    #[serde(default)]
+    pub max_agents: Option<usize>,
+    #[serde(default)]
+    pub max_processes: Option<usize>,
+    #[serde(default)]
+    pub agents: usize,
+    /// Only counted when `max_processes` is set
+    #[serde(default)]
+    pub processes: Option<usize>,
+}
+
+/// Host load, as read from `/proc` by the CLI
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Host {
+    #[serde(default)]
+    pub load: f64,
+    #[serde(default)]
+    pub cpus: usize,
+    #[serde(default)]
+    pub memory_percent: f64,
 }
 
 #[derive(Debug, Clone, Default, Deserialize)]
 pub struct MaestroOutput {
     #[serde(default)]
     pub instances: Vec<ClaudeInstance>,
+    #[serde(default)]
+    pub limits: Option<Limits>,
+    #[serde(default)]
+    pub host: Option<Host>,
 }
 
 impl MaestroOutput {
@@ -1,6 +1,7 @@
 mod instance;
 mod state;
 mod ui;
+mod worktree;
 
 use zellij_tile::prelude::*;
 
@@ -36,15 +37,23 @@ impl ZellijPlugin for State {
                 set_timeout(1.0);
                 true
             }
-            Event::RunCommandResult(exit_code, stdout, _stderr, context) => {
-                if context.get("source").map(|s| s.as_str()) == Some("instances") {
-                    if exit_code == Some(0) {
-                        let output = MaestroOutput::parse(&stdout);
-                        self.set_instances(output.instances);
-                    } else {
-                        self.set_instances(Vec::new());
+            Event::RunCommandResult(exit_code, stdout, stderr, context) => {
+                match context.get("source").map(|s| s.as_str()) {
+                    Some("instances") => {
+                        if exit_code == Some(0) {
+                            let output = MaestroOutput::parse(&stdout);
+                            self.limits = output.limits;
+                            self.host = output.host;
+                            self.set_instances(output.instances);
+                        } else {
+                            self.set_instances(Vec::new());
+                        }
+                        self.loading = false;
                     }
-                    self.loading = false;
+                    Some(source) => {
+                        self.handle_command_result(source, exit_code, &stdout, &stderr);
+                    }
+                    None => {}
                 }
                 true
             }
@@ -1,12 +1,57 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
 use zellij_tile::prelude::*;
 
-use crate::instance::ClaudeInstance;
+use crate::instance::{ClaudeInstance, Host, Limits};
+use crate::worktree::{WorktreeEntry, WorktreeOutput};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum View {
+    #[default]
+    Instances,
+    Worktrees,
+}
+
+#[derive(Debug, Clone)]
+pub enum WorktreeAction {
+    Remove { branch: String },
+    Merge { branch: String, path: String },
+}
+
+impl WorktreeAction {
+    pub fn prompt(&self) -> String {
+        match self {
+            WorktreeAction::Remove { branch } => format!("Remove '{branch}'? (y/n)"),
+            WorktreeAction::Merge { branch, .. } => format!("Merge '{branch}' and clean up? (y/n)"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub enum Mode {
+    #[default]
+    Normal,
+    /// Typing the name of a branch to create
+    NewBranch(String),
+    /// Waiting for y/n before running a worktree action
+    Confirm(WorktreeAction),
+}
 
 #[derive(Default)]
 pub struct State {
     pub instances: Vec<ClaudeInstance>,
     pub selected_index: usize,
     pub loading: bool,
+    pub view: View,
+    pub mode: Mode,
+    pub worktrees: Vec<WorktreeEntry>,
+    pub worktree_index: usize,
+    pub worktrees_loading: bool,
+    /// Feedback from the last worktree command
+    pub message: Option<String>,
+    pub limits: Option<Limits>,
+    pub host: Option<Host>,
 }
 
 impl State {
@@ -14,38 +59,133 @@ impl State {
 
     pub fn refresh_instances(&mut self) {
         self.loading = true;
-        let mut context = std::collections::BTreeMap::new();
+        let mut context = BTreeMap::new();
         context.insert("source".to_string(), "instances".to_string());
         run_command(&[Self::MAESTRO_BIN, "list", "--json"], context);
     }
 
-    pub fn set_instances(&mut self, instances: Vec<ClaudeInstance>) {
+    pub fn set_instances(&mut self, mut instances: Vec<ClaudeInstance>) {
+        // Keep each batch together (unbatched instances first) so it renders as a group
+        instances.sort_by(|a, b| a.batch.cmp(&b.batch));
         self.instances = instances;
         if self.selected_index >= self.instances.len() && !self.instances.is_empty() {
             self.selected_index = self.instances.len() - 1;
         }
     }
 
+    pub fn refresh_worktrees(&mut self) {
+        self.worktrees_loading = true;
+        let mut context = BTreeMap::new();
+        context.insert("source".to_string(), "worktrees".to_string());
+        run_command(&[Self::MAESTRO_BIN, "wt", "list", "--json"], context);
+    }
+
+    pub fn set_worktrees(&mut self, output: WorktreeOutput) {
+        self.worktrees = output.worktrees.into_iter().filter(|w| !w.is_bare).collect();
+        if self.worktree_index >= self.worktrees.len() && !self.worktrees.is_empty() {
+            self.worktree_index = self.worktrees.len() - 1;
+        }
+    }
+
+    pub fn handle_command_result(
+        &mut self,
+        source: &str,
+        exit_code: Option<i32>,
+        stdout: &[u8],
+        stderr: &[u8],
+    ) {
+        let succeeded = exit_code == Some(0);
+        match source {
+            "worktrees" => {
+                if succeeded {
+                    self.set_worktrees(WorktreeOutput::parse(stdout));
+                } else {
+                    self.set_worktrees(WorktreeOutput::default());
+                    self.message = Some(last_line(stderr));
+                }
+                self.worktrees_loading = false;
+            }
+            "worktree-layout" => {
+                if succeeded {
+                    self.message = None;
+                    new_tabs_with_layout(&String::from_utf8_lossy(stdout));
+                    hide_self();
+                } else {
+                    self.message = Some(last_line(stderr));
+                }
+                self.refresh_worktrees();
+            }
+            "worktree-action" => {
+                self.message = Some(if succeeded {
+                    last_line(stdout)
+                } else {
+                    last_line(stderr)
+                });
+                self.refresh_worktrees();
+            }
+            _ => {}
+        }
+    }
+
     pub fn handle_keys(&mut self, key: KeyWithModifier) -> bool {
+        match std::mem::take(&mut self.mode) {
+            Mode::Normal => {}
+            Mode::NewBranch(name) => return self.handle_new_branch_keys(key, name),
+            Mode::Confirm(action) => return self.handle_confirm_keys(key, action),
+        }
+
         match key.bare_key {
-            BareKey::Up | BareKey::Char('k') => {
-                if self.selected_index > 0 {
-                    self.selected_index -= 1;
+            BareKey::Tab => {
+                self.view = match self.view {
+                    View::Instances => View::Worktrees,
+                    View::Worktrees => View::Instances,
+                };
+                if self.view == View::Worktrees {
+                    self.refresh_worktrees();
                 }
                 true
             }
+            BareKey::Up | BareKey::Char('k') => {
+                let index = self.current_index_mut();
+                *index = index.saturating_sub(1);
+                true
+            }
             BareKey::Down | BareKey::Char('j') => {
-                if self.selected_index < self.instances.len().saturating_sub(1) {
-                    self.selected_index += 1;
+                let len = self.current_len();
+                let index = self.current_index_mut();
+                if *index < len.saturating_sub(1) {
+                    *index += 1;
                 }
                 true
             }
             BareKey::Enter => {
-                self.focus_selected_instance();
+                match self.view {
+                    View::Instances => self.focus_selected_instance(),
+                    View::Worktrees => self.open_selected_worktree(),
+                }
                 true
             }
             BareKey::Char('r') => {
-                self.refresh_instances();
+                match self.view {
+                    View::Instances => self.refresh_instances(),
+                    View::Worktrees => self.refresh_worktrees(),
+                }
+                true
+            }
+            BareKey::Char('n') if self.view == View::Worktrees => {
+                self.mode = Mode::NewBranch(String::new());
+                true
+            }
+            BareKey::Char('d') if self.view == View::Worktrees => {
+                if let Some((branch, _)) = self.selected_worktree_target() {
+                    self.mode = Mode::Confirm(WorktreeAction::Remove { branch });
+                }
+                true
+            }
+            BareKey::Char('m') if self.view == View::Worktrees => {
+                if let Some((branch, path)) = self.selected_worktree_target() {
+                    self.mode = Mode::Confirm(WorktreeAction::Merge { branch, path });
+                }
                 true
             }
             BareKey::Esc | BareKey::Char('q') => {
@@ -56,6 +196,54 @@ impl State {
         }
     }
 
+    fn handle_new_branch_keys(&mut self, key: KeyWithModifier, mut name: String) -> bool {
+        match key.bare_key {
+            BareKey::Enter => {
+                let name = name.trim();
+                if !name.is_empty() {
+                    self.switch_worktree(name);
+                }
+            }
+            BareKey::Esc => {}
+            BareKey::Backspace => {
+                name.pop();
+                self.mode = Mode::NewBranch(name);
+            }
+            BareKey::Char(c) if !c.is_whitespace() => {
+                name.push(c);
+                self.mode = Mode::NewBranch(name);
+            }
+            _ => self.mode = Mode::NewBranch(name),
+        }
+        true
+    }
+
+    fn handle_confirm_keys(&mut self, key: KeyWithModifier, action: WorktreeAction) -> bool {
+        if key.bare_key == BareKey::Char('y') {
+            self.run_worktree_action(&action);
+        }
+        true
+    }
+
+    fn current_len(&self) -> usize {
+        match self.view {
+            View::Instances => self.instances.len(),
+            View::Worktrees => self.worktrees.len(),
+        }
+    }
+
+    fn current_index_mut(&mut self) -> &mut usize {
+        match self.view {
+            View::Instances => &mut self.selected_index,
+            View::Worktrees => &mut self.worktree_index,
+        }
+    }
+
+    fn selected_worktree_target(&self) -> Option<(String, String)> {
+        let worktree = self.worktrees.get(self.worktree_index)?;
+        Some((worktree.branch.clone()?, worktree.path.clone()))
+    }
+
     pub fn focus_selected_instance(&self) {
         if let Some(instance) = self.instances.get(self.selected_index) {
             focus_terminal_pane(instance.pane_id, true);
@@ -63,4 +251,53 @@ impl State {
         }
     }
 
+    fn open_selected_worktree(&mut self) {
+        if let Some(branch) = self.worktrees.get(self.worktree_index).and_then(|w| w.branch.clone()) {
+            self.switch_worktree(&branch);
+        }
+    }
+
+    /// Ask the CLI for the worktree's layout (creating the worktree if needed)
+    /// and open it as a new tab once the result arrives
+    fn switch_worktree(&mut self, branch: &str) {
+        self.message = Some(format!("Opening '{branch}'..."));
+        let mut context = BTreeMap::new();
+        context.insert("source".to_string(), "worktree-layout".to_string());
+        run_command(
+            &[Self::MAESTRO_BIN, "wt", "switch", branch, "--print-layout"],
+            context,
+        );
+    }
+
+    fn run_worktree_action(&mut self, action: &WorktreeAction) {
+        let mut context = BTreeMap::new();
+        context.insert("source".to_string(), "worktree-action".to_string());
+
+        match action {
+            WorktreeAction::Remove { branch } => {
+                self.message = Some(format!("Removing '{branch}'..."));
+                run_command(&[Self::MAESTRO_BIN, "wt", "remove", branch], context);
+            }
+            WorktreeAction::Merge { branch, path } => {
+                self.message = Some(format!("Merging '{branch}'..."));
+                // Merge operates on the worktree it's run from
+                run_command_with_env_variables_and_cwd(
+                    &[Self::MAESTRO_BIN, "wt", "merge"],
+                    BTreeMap::new(),
+                    PathBuf::from(path),
+                    context,
+                );
+            }
+        }
+    }
+}
+
+fn last_line(output: &[u8]) -> String {
+    String::from_utf8_lossy(output)
+        .lines()
+        .rev()
+        .find(|line| !line.trim().is_empty())
+        .unwrap_or_default()
+        .trim()
+        .to_string()
 }
@@ -1,15 +1,81 @@
-use crate::instance::InstanceStatus;
-use crate::state::State;
+use crate::instance::{ClaudeInstance, InstanceStatus};
+use crate::state::{Mode, State, View};
 
 const RESET: &str = "\x1b[0m";
 const BOLD: &str = "\x1b[1m";
 const DIM: &str = "\x1b[2m";
 const YELLOW: &str = "\x1b[33m";
 const CYAN: &str = "\x1b[36m";
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
 const BG_GRAY: &str = "\x1b[48;5;238m";
 
-pub fn render(state: &State, _rows: usize, _cols: usize) {
-    if state.loading {
+/// Longest tool description shown next to a status icon
+const MAX_TOOL_WIDTH: usize = 40;
+
+pub fn render(state: &State, _rows: usize, cols: usize) {
+    render_header(state);
+
+    match state.view {
+        View::Instances => render_instances(state, cols),
+        View::Worktrees => render_worktrees(state),
+    }
+
+    render_footer(state);
+}
+
+fn render_header(state: &State) {
+    let view = state.view;
+    let tab = |label: &str, active: bool| {
+        if active {
+            format!("{BOLD}[{label}]{RESET}")
+        } else {
+            format!("{DIM} {label} {RESET}")
+        }
+    };
+    println!(
+        "{}{}{DIM}  (Tab){RESET}{}",
+        tab("Instances", view == View::Instances),
+        tab("Worktrees", view == View::Worktrees),
+        load_summary(state)
+    );
+}
+
+/// Agent and process counts against `[limits]`, and the host's load
+fn load_summary(state: &State) -> String {
+    let count = |n: usize, max: Option<usize>, what: &str| match max {
+        Some(max) if n >= max => format!("{RED}{n}/{max} {what}{RESET}{DIM}"),
+        Some(max) => format!("{n}/{max} {what}"),
+        None => format!("{n} {what}"),
+    };
+
+    let mut parts = Vec::new();
+    if let Some(limits) = &state.limits {
+        parts.push(count(limits.agents, limits.max_agents, "agents"));
+        if let Some(processes) = limits.processes {
+            parts.push(count(processes, limits.max_processes, "processes"));
+        }
+    }
+    if let Some(host) = &state.host {
+        parts.push(format!("load {:.1}/{}", host.load, host.cpus));
+        parts.push(format!("mem {:.0}%", host.memory_percent));
+    }
+    if parts.is_empty() {
+        return String::new();
+    }
+    format!("  {DIM}{}{RESET}", parts.join(" · "))
+}
+
+fn status_color(status: InstanceStatus) -> &'static str {
+    match status {
+        InstanceStatus::Running => YELLOW,
+        InstanceStatus::Waiting => CYAN,
+        InstanceStatus::Permission => RED,
+    }
+}
+
+fn render_instances(state: &State, cols: usize) {
+    if state.loading && state.instances.is_empty() {
         println!("Loading...");
         return;
     }
@@ -19,18 +85,111 @@ pub fn render(state: &State, _rows: usize, _cols: usize) {
         return;
     }
 
+    let mut current_batch = None;
     for (i, instance) in state.instances.iter().enumerate() {
+        if let Some(batch) = &instance.batch
+            && current_batch != Some(batch)
+        {
+            current_batch = Some(batch);
+            println!("{DIM}── {batch} ──{RESET}");
+        }
+
         let is_selected = i == state.selected_index;
         let icon = instance.status.icon();
-        let icon_color = match instance.status {
-            InstanceStatus::Running => YELLOW,
-            InstanceStatus::Waiting => CYAN,
-        };
+        let icon_color = status_color(instance.status);
+
+        let agent = instance
+            .agent_label()
+            .map(|agent| format!(" [{agent}]"))
+            .unwrap_or_default();
+
+        let usage = instance
+            .transcript
+            .as_ref()
+            .map(|t| format!(" {DIM}${:.2} · {} turns{RESET}", t.cost_usd, t.turns))
+            .unwrap_or_default();
+
+        let tool = current_tool(instance);
+
+        if is_selected {
+            println!("{BG_GRAY}{BOLD}▶ {icon_color}{icon}{tool}{RESET}{BG_GRAY}{BOLD} {}{agent} {DIM}(pane {}){RESET}{usage}", instance.folder, instance.pane_id);
+            // What the agent said last (or was asked, before it replies)
+            if let Some(transcript) = &instance.transcript
+                && let Some(text) = transcript.last_message.as_ref().or(transcript.last_prompt.as_ref())
+            {
+                println!("    {DIM}{}{RESET}", first_line(text, cols.saturating_sub(4)));
+            }
+        } else {
+            println!("  {icon_color}{icon}{tool}{RESET} {}{agent} {DIM}(pane {}){RESET}{usage}", instance.folder, instance.pane_id);
+        }
+    }
+}
+
+/// The running tool, to show after the status icon
+fn current_tool(instance: &ClaudeInstance) -> String {
+    match &instance.current_tool {
+        Some(tool) if instance.status != InstanceStatus::Waiting => {
+            format!(" {}", first_line(&tool.description, MAX_TOOL_WIDTH))
+        }
+        _ => String::new(),
+    }
+}
+
+/// First line of `text`, cut to `width` characters
+fn first_line(text: &str, width: usize) -> String {
+    let line = text.lines().next().unwrap_or_default();
+    if line.chars().count() <= width {
+        return line.to_string();
+    }
+    let mut cut: String = line.chars().take(width.saturating_sub(1)).collect();
+    cut.push('…');
+    cut
+}
+
+fn render_worktrees(state: &State) {
+    if state.worktrees_loading && state.worktrees.is_empty() {
+        println!("Loading...");
+        return;
+    }
+
+    if state.worktrees.is_empty() {
+        println!("No worktrees");
+        return;
+    }
+
+    for (i, worktree) in state.worktrees.iter().enumerate() {
+        let is_selected = i == state.worktree_index;
+
+        let mut details = String::new();
+        if let (Some(ahead), Some(behind)) = (worktree.ahead, worktree.behind) {
+            details.push_str(&format!(" {GREEN}↑{ahead}{RESET}{DIM}/{RESET}{RED}↓{behind}{RESET}"));
+        }
+        for instance in &worktree.instances {
+            let color = status_color(instance.status);
+            details.push_str(&format!(" {color}{}{}{RESET}", instance.status.icon(), current_tool(instance)));
+        }
+        if worktree.dirty {
+            details.push_str(&format!(" {DIM}[dirty]{RESET}"));
+        }
 
         if is_selected {
-            println!("{BG_GRAY}{BOLD}▶ {icon_color}{icon}{RESET}{BG_GRAY}{BOLD} {} {DIM}(pane {}){RESET}", instance.folder, instance.pane_id);
+            println!("{BG_GRAY}{BOLD}▶ {}{RESET}{details}", worktree.name());
         } else {
-            println!("  {icon_color}{icon}{RESET} {} {DIM}(pane {}){RESET}", instance.folder, instance.pane_id);
+            println!("  {}{details}", worktree.name());
+        }
+    }
+}
+
+fn render_footer(state: &State) {
+    match &state.mode {
+        Mode::NewBranch(name) => println!("{BOLD}New branch:{RESET} {name}█"),
+        Mode::Confirm(action) => println!("{YELLOW}{}{RESET}", action.prompt()),
+        Mode::Normal => {
+            if let Some(message) = &state.message {
+                println!("{DIM}{message}{RESET}");
+            } else if state.view == View::Worktrees {
+                println!("{DIM}Enter open · n new · d remove · m merge{RESET}");
+            }
         }
     }
 }
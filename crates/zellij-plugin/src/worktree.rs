@@ -0,0 +1,36 @@
+use serde::Deserialize;
+
+use crate::instance::ClaudeInstance;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WorktreeEntry {
+    pub branch: Option<String>,
+    pub path: String,
+    #[serde(default)]
+    pub is_bare: bool,
+    #[serde(default)]
+    pub dirty: bool,
+    pub ahead: Option<u32>,
+    pub behind: Option<u32>,
+    #[serde(default)]
+    pub instances: Vec<ClaudeInstance>,
+}
+
+impl WorktreeEntry {
+    pub fn name(&self) -> &str {
+        self.branch.as_deref().unwrap_or("(detached)")
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct WorktreeOutput {
+    #[serde(default)]
+    pub worktrees: Vec<WorktreeEntry>,
+}
+
+impl WorktreeOutput {
+    pub fn parse(data: &[u8]) -> Self {
+        let json_str = String::from_utf8_lossy(data);
+        serde_json::from_str(&json_str).unwrap_or_default()
+    }
+}